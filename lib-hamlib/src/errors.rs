@@ -12,21 +12,178 @@ General Public License for more details.
 You should have received a copy of the GNU General Public License
 along with this program. If not, see <https://www.gnu.org/licenses/>
  */
-use std::ffi::{c_int, CStr};
+use crate::hamlib_raw::{
+    rig_errcode_e, rig_errcode_e_RIG_BUSBUSY, rig_errcode_e_RIG_BUSERROR, rig_errcode_e_RIG_EARG,
+    rig_errcode_e_RIG_ECONF, rig_errcode_e_RIG_EDOM, rig_errcode_e_RIG_EINTERNAL,
+    rig_errcode_e_RIG_EINVAL, rig_errcode_e_RIG_EIO, rig_errcode_e_RIG_ENAVAIL,
+    rig_errcode_e_RIG_ENIMPL, rig_errcode_e_RIG_ENOMEM, rig_errcode_e_RIG_ENTARGET,
+    rig_errcode_e_RIG_EPROTO, rig_errcode_e_RIG_ERJCTED, rig_errcode_e_RIG_ETIMEOUT,
+    rig_errcode_e_RIG_ETRUNC, rig_errcode_e_RIG_EVFO, rig_errcode_e_RIG_OK,
+};
+use std::ffi::c_int;
+use std::fmt;
 
-#[derive(Debug)]
-pub struct HamLibError<'a> {
-    pub error_code: u32,
-    pub message: &'a str,
+/// Hamlib error, mapped from the `rig_errcode_e` codes returned by the rig functions.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum HamLibError {
+    InvalidParameter,
+    InvalidConfiguration,
+    OutOfMemory,
+    NotImplemented,
+    Timeout,
+    Io,
+    Internal,
+    Protocol,
+    Rejected,
+    Truncated,
+    NotAvailable,
+    VfoNotTargetable,
+    BusError,
+    BusBusy,
+    InvalidArgument,
+    InvalidVfo,
+    OutOfDomain,
+    UnknownConfigToken(String),
+    Unknown(rig_errcode_e),
 }
 
-impl<'a> HamLibError<'a> {
-    pub(crate) fn from_hamlib_error_code(error_code: u32) -> HamLibError<'a> {
-        let char_ptr = unsafe { crate::hamlib_raw::rigerror(error_code as c_int) };
-        let str = unsafe { CStr::from_ptr(char_ptr) }.to_str().unwrap();
-        HamLibError {
-            error_code,
-            message: str,
+impl HamLibError {
+    fn all() -> &'static [(Self, rig_errcode_e)] {
+        &[
+            (Self::InvalidParameter, rig_errcode_e_RIG_EINVAL),
+            (Self::InvalidConfiguration, rig_errcode_e_RIG_ECONF),
+            (Self::OutOfMemory, rig_errcode_e_RIG_ENOMEM),
+            (Self::NotImplemented, rig_errcode_e_RIG_ENIMPL),
+            (Self::Timeout, rig_errcode_e_RIG_ETIMEOUT),
+            (Self::Io, rig_errcode_e_RIG_EIO),
+            (Self::Internal, rig_errcode_e_RIG_EINTERNAL),
+            (Self::Protocol, rig_errcode_e_RIG_EPROTO),
+            (Self::Rejected, rig_errcode_e_RIG_ERJCTED),
+            (Self::Truncated, rig_errcode_e_RIG_ETRUNC),
+            (Self::NotAvailable, rig_errcode_e_RIG_ENAVAIL),
+            (Self::VfoNotTargetable, rig_errcode_e_RIG_ENTARGET),
+            (Self::BusError, rig_errcode_e_RIG_BUSERROR),
+            (Self::BusBusy, rig_errcode_e_RIG_BUSBUSY),
+            (Self::InvalidArgument, rig_errcode_e_RIG_EARG),
+            (Self::InvalidVfo, rig_errcode_e_RIG_EVFO),
+            (Self::OutOfDomain, rig_errcode_e_RIG_EDOM),
+        ]
+    }
+
+    /// Maps a Hamlib return code. Rig functions return the error codes negated.
+    pub(crate) fn from_hamlib_error_code(error_code: c_int) -> Self {
+        let error_code = error_code.unsigned_abs() as rig_errcode_e;
+        Self::all()
+            .iter()
+            .find(|(_, code)| *code == error_code)
+            .map(|(error, _)| error.clone())
+            .unwrap_or(Self::Unknown(error_code))
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        *self == Self::Timeout
+    }
+
+    /// The rig or its Hamlib backend doesn't provide the requested feature.
+    pub fn is_not_supported(&self) -> bool {
+        matches!(
+            self,
+            Self::NotImplemented | Self::NotAvailable | Self::VfoNotTargetable
+        )
+    }
+
+    /// The link to the rig failed: it didn't answer or the answer was garbled.
+    pub fn is_link_failure(&self) -> bool {
+        matches!(
+            self,
+            Self::Timeout | Self::Io | Self::Protocol | Self::BusError | Self::BusBusy
+        )
+    }
+
+    /// The rig answered, but refused the request or its arguments.
+    pub fn is_rejected(&self) -> bool {
+        matches!(
+            self,
+            Self::Rejected
+                | Self::InvalidParameter
+                | Self::InvalidArgument
+                | Self::InvalidVfo
+                | Self::OutOfDomain
+                | Self::Truncated
+        )
+    }
+}
+
+impl fmt::Display for HamLibError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidParameter => f.write_str("invalid parameter"),
+            Self::InvalidConfiguration => f.write_str("invalid configuration"),
+            Self::OutOfMemory => f.write_str("memory shortage"),
+            Self::NotImplemented => f.write_str("feature not implemented"),
+            Self::Timeout => f.write_str("communication timed out"),
+            Self::Io => f.write_str("IO error"),
+            Self::Internal => f.write_str("internal Hamlib error"),
+            Self::Protocol => f.write_str("protocol error"),
+            Self::Rejected => f.write_str("command rejected by the rig"),
+            Self::Truncated => f.write_str("command performed, but argument truncated"),
+            Self::NotAvailable => f.write_str("feature not available"),
+            Self::VfoNotTargetable => f.write_str("target VFO unaccessible"),
+            Self::BusError => f.write_str("communication bus error"),
+            Self::BusBusy => f.write_str("communication bus collision"),
+            Self::InvalidArgument => f.write_str("invalid rig handle or pointer parameter"),
+            Self::InvalidVfo => f.write_str("invalid VFO"),
+            Self::OutOfDomain => f.write_str("argument out of domain"),
+            Self::UnknownConfigToken(token) => write!(f, "unknown configuration token '{token}'"),
+            Self::Unknown(error_code) => write!(f, "unknown Hamlib error {error_code}"),
         }
     }
 }
+
+impl std::error::Error for HamLibError {}
+
+/// Converts the return code of a Hamlib rig function.
+pub(crate) fn hamlib_result(ret: c_int) -> Result<(), HamLibError> {
+    if ret == rig_errcode_e_RIG_OK as c_int {
+        Ok(())
+    } else {
+        Err(HamLibError::from_hamlib_error_code(ret))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hamlib_result, HamLibError};
+    use crate::hamlib_raw::{
+        rig_errcode_e_RIG_ENIMPL, rig_errcode_e_RIG_ERJCTED, rig_errcode_e_RIG_ETIMEOUT,
+    };
+    use std::ffi::c_int;
+
+    #[test]
+    fn maps_negated_hamlib_error_codes() {
+        assert_eq!(hamlib_result(0), Ok(()));
+        assert_eq!(
+            hamlib_result(-(rig_errcode_e_RIG_ETIMEOUT as c_int)),
+            Err(HamLibError::Timeout)
+        );
+        assert!(
+            HamLibError::from_hamlib_error_code(-(rig_errcode_e_RIG_ENIMPL as c_int))
+                .is_not_supported()
+        );
+        assert_eq!(
+            HamLibError::from_hamlib_error_code(-1000),
+            HamLibError::Unknown(1000)
+        );
+    }
+
+    #[test]
+    fn separates_link_failures_from_rejections() {
+        let timeout = HamLibError::from_hamlib_error_code(-(rig_errcode_e_RIG_ETIMEOUT as c_int));
+        assert!(timeout.is_link_failure());
+        assert!(!timeout.is_rejected());
+        let rejected = HamLibError::from_hamlib_error_code(-(rig_errcode_e_RIG_ERJCTED as c_int));
+        assert!(rejected.is_rejected());
+        assert!(!rejected.is_link_failure());
+        assert!(!HamLibError::NotImplemented.is_link_failure());
+    }
+}
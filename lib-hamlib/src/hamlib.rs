@@ -1,13 +1,14 @@
-use crate::errors::HamLibError;
+use crate::errors::{hamlib_result, HamLibError};
 use crate::hamlib_raw;
 use crate::hamlib_raw::{
-    freq_range_t, rig_caps, rig_debug_level_e, rig_debug_level_e_RIG_DEBUG_BUG,
-    rig_debug_level_e_RIG_DEBUG_CACHE, rig_debug_level_e_RIG_DEBUG_ERR,
-    rig_debug_level_e_RIG_DEBUG_NONE, rig_debug_level_e_RIG_DEBUG_TRACE,
-    rig_debug_level_e_RIG_DEBUG_VERBOSE, rig_debug_level_e_RIG_DEBUG_WARN, rig_errcode_e_RIG_OK,
-    rig_load_all_backends, rmode_t, vfo_op_t, RIG_CONF_END, RIG_MODE_NONE,
+    filter_list, freq_range_t, gran_t, rig_caps, rig_debug_level_e,
+    rig_debug_level_e_RIG_DEBUG_BUG, rig_debug_level_e_RIG_DEBUG_CACHE,
+    rig_debug_level_e_RIG_DEBUG_ERR, rig_debug_level_e_RIG_DEBUG_NONE,
+    rig_debug_level_e_RIG_DEBUG_TRACE, rig_debug_level_e_RIG_DEBUG_VERBOSE,
+    rig_debug_level_e_RIG_DEBUG_WARN, rig_load_all_backends, rmode_t, setting_t, vfo_op_t,
+    RIG_CONF_END, RIG_MODE_NONE,
 };
-use crate::rig::{Rig, RigVfoOperation};
+use crate::rig::{Rig, RigFunction, RigLevel, RigVfoOperation};
 use std::collections::HashMap;
 use std::ffi::{c_void, CStr, CString};
 use std::os::raw::{c_int, c_long};
@@ -16,17 +17,65 @@ use std::sync::{Mutex, OnceLock};
 
 type HamlibToken = c_long;
 
-#[derive(Clone, Debug)]
+const RIG_TARGETABLE_FREQ: c_int = 1 << 0;
+const RIG_TARGETABLE_MODE: c_int = 1 << 1;
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct RigCaps {
     pub rig_model: u32,
     pub model_name: String,
     pub manufacturer_name: String,
     pub vfo_ops: Vec<RigVfoOperation>,
+    // Frequency and mode of any VFO are readable without switching the current VFO
+    pub targetable_freq: bool,
+    pub targetable_mode: bool,
+    // Largest RIT and XIT offsets in Hz, 0 when the rig has none
+    pub max_rit_hz: u32,
+    pub max_xit_hz: u32,
+    // The backend can read the split state back from the rig
+    pub can_get_split: bool,
+    // The backend can read the selected antennas back from the rig
+    pub can_get_antennas: bool,
+    pub get_levels: Vec<RigLevel>,
+    pub set_levels: Vec<RigLevelRange>,
+    pub filters: Vec<RigFilter>,
+    pub get_functions: Vec<RigFunction>,
+    pub set_functions: Vec<RigFunction>,
     pub rx_frequency_ranges: Vec<RigFrequencyRange>,
     pub tx_frequency_ranges: Vec<RigFrequencyRange>,
 }
 
-#[derive(Clone, Debug)]
+/// Filter width available for the modes.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RigFilter {
+    pub modes: Vec<RigMode>,
+    // 0 when the rig accepts any width
+    pub width_hz: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RigLevelRange {
+    pub level: RigLevel,
+    // min and max are both 0 when the rig doesn't declare the range
+    pub min: f32,
+    pub max: f32,
+    pub step: f32,
+}
+
+impl RigLevelRange {
+    /// Float levels without a declared range go from 0.0 to 1.0 in Hamlib.
+    pub fn contains(&self, value: f32) -> bool {
+        if self.max > self.min {
+            (self.min..=self.max).contains(&value)
+        } else if self.level.is_float() {
+            (0.0..=1.0).contains(&value)
+        } else {
+            true
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct RigFrequencyRange {
     pub region: u8,
     pub lower_frequency_hz: u64,
@@ -282,6 +331,17 @@ pub(crate) unsafe fn rigcaps_mapper(caps: *const rig_caps) -> RigCaps {
         model_name,
         manufacturer_name,
         vfo_ops: vfo_ops_mapper((*caps).vfo_ops),
+        targetable_freq: (*caps).targetable_vfo & RIG_TARGETABLE_FREQ != 0,
+        targetable_mode: (*caps).targetable_vfo & RIG_TARGETABLE_MODE != 0,
+        max_rit_hz: (*caps).max_rit.max(0) as u32,
+        max_xit_hz: (*caps).max_xit.max(0) as u32,
+        can_get_split: (*caps).get_split_vfo.is_some(),
+        can_get_antennas: (*caps).get_ant.is_some(),
+        get_levels: levels_mapper((*caps).has_get_level),
+        set_levels: level_ranges_mapper((*caps).has_set_level, &(*caps).level_gran),
+        filters: filters_mapper(&(*caps).filters),
+        get_functions: functions_mapper((*caps).has_get_func),
+        set_functions: functions_mapper((*caps).has_set_func),
         rx_frequency_ranges: freq_ranges_mapper(&[
             (1, &(*caps).rx_range_list1),
             (2, &(*caps).rx_range_list2),
@@ -313,6 +373,65 @@ fn vfo_ops_mapper(vfo_ops: vfo_op_t) -> Vec<RigVfoOperation> {
         .collect()
 }
 
+fn levels_mapper(levels: setting_t) -> Vec<RigLevel> {
+    RigLevel::all()
+        .iter()
+        .filter_map(|(level, bit)| {
+            if levels & *bit == *bit {
+                Some(*level)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn filters_mapper(filters: &[filter_list]) -> Vec<RigFilter> {
+    filters
+        .iter()
+        .take_while(|filter| filter.modes != RIG_MODE_NONE as rmode_t)
+        .map(|filter| RigFilter {
+            modes: modes_mapper(filter.modes),
+            width_hz: filter.width.max(0) as u32,
+        })
+        .collect()
+}
+
+fn functions_mapper(functions: setting_t) -> Vec<RigFunction> {
+    RigFunction::all()
+        .iter()
+        .filter_map(|(function, bit)| {
+            if functions & *bit == *bit {
+                Some(*function)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn level_ranges_mapper(levels: setting_t, level_gran: &[gran_t]) -> Vec<RigLevelRange> {
+    levels_mapper(levels)
+        .into_iter()
+        .map(|level| {
+            let gran = &level_gran[level.as_hamlib_level().trailing_zeros() as usize];
+            let (min, max, step) = unsafe {
+                if level.is_float() {
+                    (gran.min.f, gran.max.f, gran.step.f)
+                } else {
+                    (gran.min.i as f32, gran.max.i as f32, gran.step.i as f32)
+                }
+            };
+            RigLevelRange {
+                level,
+                min,
+                max,
+                step,
+            }
+        })
+        .collect()
+}
+
 fn freq_ranges_mapper(range_lists: &[(u8, &[freq_range_t; 30])]) -> Vec<RigFrequencyRange> {
     range_lists
         .iter()
@@ -510,59 +629,62 @@ impl Hamlib {
         &mut self,
         rig_model: u32,
         config: HashMap<String, String>,
-    ) -> Result<Rig, HamLibError<'_>> {
+    ) -> Result<Rig, HamLibError> {
+        let mut rig = self.rig_init(rig_model, config)?;
+        rig.reopen()?;
+        Ok(rig)
+    }
+
+    /// Creates and configures the rig handle without opening its port, see `Rig::reopen`.
+    pub fn rig_init(
+        &mut self,
+        rig_model: u32,
+        config: HashMap<String, String>,
+    ) -> Result<Rig, HamLibError> {
         unsafe {
             let rig = hamlib_raw::rig_init(rig_model);
+            if rig.is_null() {
+                return Err(HamLibError::InvalidConfiguration);
+            }
+            // From here the handle is released by Drop, including on errors
+            let rig = Rig { rig, open: false };
             for (key, value) in config {
-                let token = rig_token_lookup(rig, &key)?;
-                rig_set_conf(rig, token, &value)?;
+                let token = rig_token_lookup(rig.rig, &key)?;
+                rig_set_conf(rig.rig, token, &value)?;
             }
 
-            let open_result = hamlib_raw::rig_open(rig) as u32;
-            if open_result == rig_errcode_e_RIG_OK {
-                return Ok(Rig { rig });
-            }
-            Err(HamLibError::from_hamlib_error_code(open_result))
+            Ok(rig)
         }
     }
 }
 
-unsafe fn rig_token_lookup<'a>(
+unsafe fn rig_token_lookup(
     rig: *mut hamlib_raw::RIG,
     name: &str,
-) -> Result<HamlibToken, HamLibError<'a>> {
-    let name = CString::new(name).unwrap();
-    let token = unsafe { hamlib_raw::rig_token_lookup(rig, name.as_ptr()) };
+) -> Result<HamlibToken, HamLibError> {
+    let token_name = CString::new(name).unwrap();
+    let token = unsafe { hamlib_raw::rig_token_lookup(rig, token_name.as_ptr()) };
     if token == RIG_CONF_END as HamlibToken {
-        return Err(HamLibError {
-            error_code: RIG_CONF_END,
-            message: "unknown hamlib config token",
-        });
+        return Err(HamLibError::UnknownConfigToken(name.to_string()));
     }
 
     Ok(token)
 }
 
-unsafe fn rig_set_conf<'a>(
+unsafe fn rig_set_conf(
     rig: *mut hamlib_raw::RIG,
     token: HamlibToken,
     value: &str,
-) -> Result<(), HamLibError<'a>> {
+) -> Result<(), HamLibError> {
     let value = CString::new(value).unwrap();
-    let result = unsafe { hamlib_raw::rig_set_conf(rig, token, value.as_ptr()) as u32 };
-
-    if result == rig_errcode_e_RIG_OK {
-        Ok(())
-    } else {
-        Err(HamLibError::from_hamlib_error_code(result))
-    }
+    hamlib_result(unsafe { hamlib_raw::rig_set_conf(rig, token, value.as_ptr()) })
 }
 
 #[cfg(test)]
 mod tests {
-    use super::vfo_ops_mapper;
+    use super::{levels_mapper, vfo_ops_mapper, RigLevelRange};
     use crate::hamlib_raw::{vfo_op_t_RIG_OP_BAND_UP, vfo_op_t_RIG_OP_CPY, vfo_op_t_RIG_OP_TUNE};
-    use crate::rig::RigVfoOperation;
+    use crate::rig::{RigLevel, RigVfoOperation};
 
     #[test]
     fn maps_vfo_ops_bitfield_to_enum_list() {
@@ -578,4 +700,30 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn maps_levels_bitfield_to_enum_list() {
+        // RIG_LEVEL_SWR | RIG_LEVEL_STRENGTH | RIG_LEVEL_VD_METER
+        let mapped = levels_mapper((1 << 28) | (1 << 30) | (1 << 34));
+
+        assert_eq!(
+            mapped,
+            vec![RigLevel::Strength, RigLevel::Swr, RigLevel::VdMeter]
+        );
+    }
+
+    #[test]
+    fn level_range_defaults_to_unit_range_for_float_levels() {
+        let range = |level, min, max| RigLevelRange {
+            level,
+            min,
+            max,
+            step: 0.0,
+        };
+
+        assert!(range(RigLevel::KeyerSpeed, 4.0, 60.0).contains(60.0));
+        assert!(!range(RigLevel::KeyerSpeed, 4.0, 60.0).contains(2.0));
+        assert!(range(RigLevel::AfGain, 0.0, 0.0).contains(1.0));
+        assert!(!range(RigLevel::AfGain, 0.0, 0.0).contains(1.5));
+    }
 }
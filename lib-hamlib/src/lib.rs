@@ -12,7 +12,7 @@ General Public License for more details.
 You should have received a copy of the GNU General Public License
 along with this program. If not, see <https://www.gnu.org/licenses/>
  */
-mod errors;
+pub mod errors;
 pub mod hamlib;
 mod hamlib_raw;
 pub mod rig;
@@ -20,6 +20,7 @@ pub mod rig;
 #[cfg(test)]
 mod tests {
     use crate::hamlib;
+    use crate::rig::{RigFunction, RigLevel, RigPassband, RigPtt};
     use std::collections::HashMap;
     use std::sync::{Mutex, MutexGuard, OnceLock};
 
@@ -54,14 +55,128 @@ mod tests {
         assert!(rig.is_ok())
     }
 
+    #[test]
+    fn init_rig_without_opening() {
+        let _guard = hamlib_test_guard();
+        let mut hamlib = hamlib::Hamlib::new();
+        let mut rig = hamlib.rig_init(1, HashMap::new()).unwrap();
+
+        assert!(!rig.is_open());
+        assert!(rig.caps().is_some());
+        rig.reopen().unwrap();
+        assert!(rig.is_open());
+    }
+
+    #[test]
+    fn close_and_reopen_rig() {
+        let _guard = hamlib_test_guard();
+        let mut hamlib = hamlib::Hamlib::new();
+        let mut rig = hamlib.rig_connect(1, HashMap::new()).unwrap();
+
+        rig.close().unwrap();
+        assert!(!rig.is_open());
+        // Closing twice is a no-op
+        rig.close().unwrap();
+
+        rig.reopen().unwrap();
+        assert!(rig.is_open());
+        rig.set_freq(0, 100.0).unwrap();
+        assert_eq!(rig.get_freq(0).unwrap(), 100.0);
+    }
+
     #[test]
     fn get_freq() {
         let _guard = hamlib_test_guard();
         let mut hamlib = hamlib::Hamlib::new();
         let rig = hamlib.rig_connect(1, HashMap::new()).unwrap();
-        rig.set_freq(0, 100.0);
+        rig.set_freq(0, 100.0).unwrap();
         let freq = rig.get_freq(0).unwrap();
 
         assert_eq!(freq, 100.0);
     }
+
+    #[test]
+    fn set_and_get_ptt() {
+        let _guard = hamlib_test_guard();
+        let mut hamlib = hamlib::Hamlib::new();
+        let rig = hamlib.rig_connect(1, HashMap::new()).unwrap();
+        rig.set_ptt(0, RigPtt::On).unwrap();
+        assert_eq!(rig.get_ptt(0).unwrap(), RigPtt::On);
+
+        rig.set_ptt(0, RigPtt::Off).unwrap();
+        assert_eq!(rig.get_ptt(0).unwrap(), RigPtt::Off);
+    }
+
+    #[test]
+    fn set_and_get_split() {
+        let _guard = hamlib_test_guard();
+        let mut hamlib = hamlib::Hamlib::new();
+        let rig = hamlib.rig_connect(1, HashMap::new()).unwrap();
+        // RIG_VFO_B
+        rig.set_split_vfo(0, true, 1 << 1).unwrap();
+        rig.set_split_freq(0, 14_025_000.0).unwrap();
+
+        assert!(rig.get_split_vfo(0).unwrap().0);
+        assert_eq!(rig.get_split_freq(0).unwrap(), 14_025_000.0);
+
+        rig.set_split_vfo(0, false, 1 << 1).unwrap();
+        assert!(!rig.get_split_vfo(0).unwrap().0);
+    }
+
+    #[test]
+    fn set_and_get_rit_and_xit() {
+        let _guard = hamlib_test_guard();
+        let mut hamlib = hamlib::Hamlib::new();
+        let rig = hamlib.rig_connect(1, HashMap::new()).unwrap();
+        rig.set_rit(0, -120).unwrap();
+        rig.set_xit(0, 500).unwrap();
+
+        assert_eq!(rig.get_rit(0).unwrap(), -120);
+        assert_eq!(rig.get_xit(0).unwrap(), 500);
+    }
+
+    #[test]
+    fn set_and_get_level() {
+        let _guard = hamlib_test_guard();
+        let mut hamlib = hamlib::Hamlib::new();
+        let rig = hamlib.rig_connect(1, HashMap::new()).unwrap();
+        rig.set_level(0, RigLevel::AfGain, 0.5).unwrap();
+        rig.set_level(0, RigLevel::KeyerSpeed, 24.0).unwrap();
+
+        assert_eq!(rig.get_level(0, RigLevel::AfGain).unwrap(), 0.5);
+        assert_eq!(rig.get_level(0, RigLevel::KeyerSpeed).unwrap(), 24.0);
+    }
+
+    #[test]
+    fn set_and_get_func() {
+        let _guard = hamlib_test_guard();
+        let mut hamlib = hamlib::Hamlib::new();
+        let rig = hamlib.rig_connect(1, HashMap::new()).unwrap();
+        rig.set_func(0, RigFunction::NoiseBlanker, true).unwrap();
+        assert!(rig.get_func(0, RigFunction::NoiseBlanker).unwrap());
+
+        rig.set_func(0, RigFunction::NoiseBlanker, false).unwrap();
+        assert!(!rig.get_func(0, RigFunction::NoiseBlanker).unwrap());
+    }
+
+    #[test]
+    fn set_mode_with_passband() {
+        let _guard = hamlib_test_guard();
+        let mut hamlib = hamlib::Hamlib::new();
+        let rig = hamlib.rig_connect(1, HashMap::new()).unwrap();
+        rig.set_mode(0, "USB", RigPassband::Hz(1800)).unwrap();
+
+        assert_eq!(rig.get_mode(0).unwrap(), ("USB".to_string(), 1800));
+    }
+
+    #[test]
+    fn set_and_get_antenna() {
+        let _guard = hamlib_test_guard();
+        let mut hamlib = hamlib::Hamlib::new();
+        let rig = hamlib.rig_connect(1, HashMap::new()).unwrap();
+        // RIG_ANT_2
+        rig.set_ant(0, 1 << 1, 0).unwrap();
+
+        assert_eq!(rig.get_ant(0).unwrap().current, 1 << 1);
+    }
 }
@@ -12,15 +12,16 @@ General Public License for more details.
 You should have received a copy of the GNU General Public License
 along with this program. If not, see <https://www.gnu.org/licenses/>
  */
-use crate::errors::HamLibError;
+use crate::errors::{hamlib_result, HamLibError};
 use crate::hamlib::{rigcaps_mapper, RigCaps};
 use crate::hamlib_raw;
 use crate::hamlib_raw::{
-    freq_t, pbwidth_t, rig_errcode_e_RIG_OK, rmode_t, value_t, vfo_op_t, vfo_op_t_RIG_OP_BAND_DOWN,
-    vfo_op_t_RIG_OP_BAND_UP, vfo_op_t_RIG_OP_CPY, vfo_op_t_RIG_OP_DOWN, vfo_op_t_RIG_OP_FROM_VFO,
-    vfo_op_t_RIG_OP_LEFT, vfo_op_t_RIG_OP_MCL, vfo_op_t_RIG_OP_RIGHT, vfo_op_t_RIG_OP_TOGGLE,
-    vfo_op_t_RIG_OP_TO_VFO, vfo_op_t_RIG_OP_TUNE, vfo_op_t_RIG_OP_UP, vfo_op_t_RIG_OP_XCHG, vfo_t,
-    RIG, RIG_MODE_NONE,
+    ant_t, freq_t, pbwidth_t, ptt_t, ptt_t_RIG_PTT_OFF, ptt_t_RIG_PTT_ON, ptt_t_RIG_PTT_ON_DATA,
+    ptt_t_RIG_PTT_ON_MIC, rmode_t, setting_t, shortfreq_t, split_t, split_t_RIG_SPLIT_OFF,
+    split_t_RIG_SPLIT_ON, value_t, vfo_op_t, vfo_op_t_RIG_OP_BAND_DOWN, vfo_op_t_RIG_OP_BAND_UP,
+    vfo_op_t_RIG_OP_CPY, vfo_op_t_RIG_OP_DOWN, vfo_op_t_RIG_OP_FROM_VFO, vfo_op_t_RIG_OP_LEFT,
+    vfo_op_t_RIG_OP_MCL, vfo_op_t_RIG_OP_RIGHT, vfo_op_t_RIG_OP_TOGGLE, vfo_op_t_RIG_OP_TO_VFO,
+    vfo_op_t_RIG_OP_TUNE, vfo_op_t_RIG_OP_UP, vfo_op_t_RIG_OP_XCHG, vfo_t, RIG, RIG_MODE_NONE,
 };
 use std::ffi::{c_void, CStr, CString};
 use std::marker::PhantomData;
@@ -54,6 +55,30 @@ const RIG_BANDSELECT_9CM: u32 = 33554432;
 const RIG_BANDSELECT_5CM: u32 = 67108864;
 const RIG_BANDSELECT_3CM: u32 = 134217728;
 const RIG_PARM_BANDSELECT: u64 = 1024;
+const RIG_ANT_CURR: ant_t = 1 << 31;
+const RIG_FUNC_NB: setting_t = 1 << 1;
+const RIG_FUNC_COMP: setting_t = 1 << 2;
+const RIG_FUNC_VOX: setting_t = 1 << 3;
+const RIG_FUNC_TONE: setting_t = 1 << 4;
+const RIG_FUNC_ANF: setting_t = 1 << 8;
+const RIG_FUNC_NR: setting_t = 1 << 9;
+const RIG_FUNC_LOCK: setting_t = 1 << 16;
+const RIG_FUNC_TUNER: setting_t = 1 << 30;
+const RIG_LEVEL_AF: setting_t = 1 << 3;
+const RIG_LEVEL_RF: setting_t = 1 << 4;
+const RIG_LEVEL_SQL: setting_t = 1 << 5;
+const RIG_LEVEL_NR: setting_t = 1 << 8;
+const RIG_LEVEL_RFPOWER: setting_t = 1 << 12;
+const RIG_LEVEL_MICGAIN: setting_t = 1 << 13;
+const RIG_LEVEL_KEYSPD: setting_t = 1 << 14;
+const RIG_LEVEL_SWR: setting_t = 1 << 28;
+const RIG_LEVEL_ALC: setting_t = 1 << 29;
+const RIG_LEVEL_STRENGTH: setting_t = 1 << 30;
+const RIG_LEVEL_RFPOWER_METER: setting_t = 1 << 32;
+const RIG_LEVEL_COMP_METER: setting_t = 1 << 33;
+const RIG_LEVEL_VD_METER: setting_t = 1 << 34;
+const RIG_LEVEL_ID_METER: setting_t = 1 << 35;
+const RIG_LEVEL_NB: setting_t = 1 << 38;
 pub struct CCallback<'closure> {
     pub function: unsafe extern "C" fn(
         arg1: *mut RIG,
@@ -107,8 +132,10 @@ impl<'closure> CCallback<'closure> {
         (*cb)(freq, vfo)
     }
 }
+/// Hamlib rig handle, closed and released on drop.
 pub struct Rig {
     pub(crate) rig: *mut RIG,
+    pub(crate) open: bool,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -166,6 +193,197 @@ impl RigVfoOperation {
     }
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RigPtt {
+    Off,
+    On,
+    OnMic,
+    OnData,
+}
+
+impl RigPtt {
+    fn as_hamlib_ptt(self) -> ptt_t {
+        match self {
+            Self::Off => ptt_t_RIG_PTT_OFF,
+            Self::On => ptt_t_RIG_PTT_ON,
+            Self::OnMic => ptt_t_RIG_PTT_ON_MIC,
+            Self::OnData => ptt_t_RIG_PTT_ON_DATA,
+        }
+    }
+
+    fn from_hamlib_ptt(ptt: ptt_t) -> Self {
+        if ptt == ptt_t_RIG_PTT_ON {
+            Self::On
+        } else if ptt == ptt_t_RIG_PTT_ON_MIC {
+            Self::OnMic
+        } else if ptt == ptt_t_RIG_PTT_ON_DATA {
+            Self::OnData
+        } else {
+            Self::Off
+        }
+    }
+
+    pub fn is_keyed(self) -> bool {
+        self != Self::Off
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RigLevel {
+    // Output power setting, from 0.0 to 1.0
+    RfPower,
+    AfGain,
+    RfGain,
+    Squelch,
+    MicGain,
+    // Keyer speed, in WPM
+    KeyerSpeed,
+    NoiseReduction,
+    NoiseBlanker,
+    // S-meter, in dB relative to S9
+    Strength,
+    // Output power, from 0.0 to 1.0
+    RfPowerMeter,
+    Swr,
+    Alc,
+    // Speech compression, in dB
+    CompMeter,
+    // Supply voltage, in V
+    VdMeter,
+    // Drain current, in A
+    IdMeter,
+}
+
+impl RigLevel {
+    pub(crate) fn all() -> &'static [(Self, setting_t)] {
+        &[
+            (Self::RfPower, RIG_LEVEL_RFPOWER),
+            (Self::AfGain, RIG_LEVEL_AF),
+            (Self::RfGain, RIG_LEVEL_RF),
+            (Self::Squelch, RIG_LEVEL_SQL),
+            (Self::MicGain, RIG_LEVEL_MICGAIN),
+            (Self::KeyerSpeed, RIG_LEVEL_KEYSPD),
+            (Self::NoiseReduction, RIG_LEVEL_NR),
+            (Self::NoiseBlanker, RIG_LEVEL_NB),
+            (Self::Strength, RIG_LEVEL_STRENGTH),
+            (Self::RfPowerMeter, RIG_LEVEL_RFPOWER_METER),
+            (Self::Swr, RIG_LEVEL_SWR),
+            (Self::Alc, RIG_LEVEL_ALC),
+            (Self::CompMeter, RIG_LEVEL_COMP_METER),
+            (Self::VdMeter, RIG_LEVEL_VD_METER),
+            (Self::IdMeter, RIG_LEVEL_ID_METER),
+        ]
+    }
+
+    pub(crate) fn as_hamlib_level(self) -> setting_t {
+        match self {
+            Self::RfPower => RIG_LEVEL_RFPOWER,
+            Self::AfGain => RIG_LEVEL_AF,
+            Self::RfGain => RIG_LEVEL_RF,
+            Self::Squelch => RIG_LEVEL_SQL,
+            Self::MicGain => RIG_LEVEL_MICGAIN,
+            Self::KeyerSpeed => RIG_LEVEL_KEYSPD,
+            Self::NoiseReduction => RIG_LEVEL_NR,
+            Self::NoiseBlanker => RIG_LEVEL_NB,
+            Self::Strength => RIG_LEVEL_STRENGTH,
+            Self::RfPowerMeter => RIG_LEVEL_RFPOWER_METER,
+            Self::Swr => RIG_LEVEL_SWR,
+            Self::Alc => RIG_LEVEL_ALC,
+            Self::CompMeter => RIG_LEVEL_COMP_METER,
+            Self::VdMeter => RIG_LEVEL_VD_METER,
+            Self::IdMeter => RIG_LEVEL_ID_METER,
+        }
+    }
+
+    // Hamlib stores these levels in the float member of value_t
+    pub fn is_float(self) -> bool {
+        !matches!(self, Self::Strength | Self::KeyerSpeed)
+    }
+
+    /// Read only levels.
+    pub fn is_meter(self) -> bool {
+        matches!(
+            self,
+            Self::Strength
+                | Self::RfPowerMeter
+                | Self::Swr
+                | Self::Alc
+                | Self::CompMeter
+                | Self::VdMeter
+                | Self::IdMeter
+        )
+    }
+
+    /// Meters only reading something while transmitting.
+    pub fn is_transmit_meter(self) -> bool {
+        matches!(
+            self,
+            Self::RfPowerMeter | Self::Swr | Self::Alc | Self::CompMeter | Self::IdMeter
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RigFunction {
+    NoiseBlanker,
+    NoiseReduction,
+    AutoNotch,
+    Vox,
+    Compressor,
+    // CTCSS tone encoder
+    Tone,
+    Lock,
+    Tuner,
+}
+
+impl RigFunction {
+    pub(crate) fn all() -> &'static [(Self, setting_t)] {
+        &[
+            (Self::NoiseBlanker, RIG_FUNC_NB),
+            (Self::NoiseReduction, RIG_FUNC_NR),
+            (Self::AutoNotch, RIG_FUNC_ANF),
+            (Self::Vox, RIG_FUNC_VOX),
+            (Self::Compressor, RIG_FUNC_COMP),
+            (Self::Tone, RIG_FUNC_TONE),
+            (Self::Lock, RIG_FUNC_LOCK),
+            (Self::Tuner, RIG_FUNC_TUNER),
+        ]
+    }
+
+    fn as_hamlib_func(self) -> setting_t {
+        match self {
+            Self::NoiseBlanker => RIG_FUNC_NB,
+            Self::NoiseReduction => RIG_FUNC_NR,
+            Self::AutoNotch => RIG_FUNC_ANF,
+            Self::Vox => RIG_FUNC_VOX,
+            Self::Compressor => RIG_FUNC_COMP,
+            Self::Tone => RIG_FUNC_TONE,
+            Self::Lock => RIG_FUNC_LOCK,
+            Self::Tuner => RIG_FUNC_TUNER,
+        }
+    }
+}
+
+/// Antennas as Hamlib antenna flags, and the antenna option.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RigAntennas {
+    pub current: ant_t,
+    pub tx: ant_t,
+    pub rx: ant_t,
+    // Backend specific, the RX-only antenna selection on most rigs
+    pub option: i32,
+}
+
+/// Passband width applied with a mode.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RigPassband {
+    // Narrow, normal and wide widths are chosen by the backend for the mode
+    Narrow,
+    Normal,
+    Wide,
+    Hz(u32),
+}
+
 // SAFETY: Rig owns an opaque hamlib handle. Callers that share it across
 // threads must provide synchronization around hamlib calls.
 unsafe impl Send for Rig {}
@@ -173,6 +391,43 @@ unsafe impl Send for Rig {}
 pub type RigFreqCallback = fn();
 
 impl Rig {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Closes the rig port. The handle stays valid and may be opened again with `reopen`.
+    pub fn close(&mut self) -> Result<(), HamLibError> {
+        if !self.open {
+            return Ok(());
+        }
+        self.open = false;
+
+        unsafe { hamlib_result(hamlib_raw::rig_close(self.rig)) }
+    }
+
+    /// Closes the rig port if it is open, then opens it again with the same configuration.
+    pub fn reopen(&mut self) -> Result<(), HamLibError> {
+        if self.open {
+            // The port is usually already gone when reopening, a failed close is expected
+            let _ = self.close();
+        }
+
+        unsafe {
+            hamlib_result(hamlib_raw::rig_open(self.rig))?;
+        }
+        self.open = true;
+        Ok(())
+    }
+
+    /// VFOs of the rig, as single Hamlib VFO flags. Known once the rig is open.
+    pub fn vfos(&self) -> Vec<vfo_t> {
+        let vfo_list = unsafe { (*self.rig).state.vfo_list };
+        (0..vfo_t::BITS)
+            .map(|bit| 1 << bit)
+            .filter(|vfo| vfo_list & vfo != 0)
+            .collect()
+    }
+
     pub fn caps(&self) -> Option<RigCaps> {
         unsafe {
             let caps = (*self.rig).caps;
@@ -196,98 +451,319 @@ impl Rig {
         }
     }
 
-    pub fn set_freq(&self, vfo: u32, freq: freq_t) {
+    pub fn set_freq(&self, vfo: u32, freq: freq_t) -> Result<(), HamLibError> {
+        unsafe { hamlib_result(hamlib_raw::rig_set_freq(self.rig, vfo, freq)) }
+    }
+
+    pub fn set_mode(&self, vfo: u32, mode: &str, passband: RigPassband) -> Result<(), HamLibError> {
+        let mode = parse_mode(mode)?;
+
         unsafe {
-            hamlib_raw::rig_set_freq(self.rig, vfo, freq);
+            let width: pbwidth_t = match passband {
+                RigPassband::Narrow => hamlib_raw::rig_passband_narrow(self.rig, mode),
+                RigPassband::Normal => hamlib_raw::rig_passband_normal(self.rig, mode),
+                RigPassband::Wide => hamlib_raw::rig_passband_wide(self.rig, mode),
+                RigPassband::Hz(width) => width as pbwidth_t,
+            };
+            hamlib_result(hamlib_raw::rig_set_mode(self.rig, vfo, mode, width))
         }
     }
 
-    pub fn set_mode(&self, vfo: u32, mode: &str) -> Result<(), HamLibError<'_>> {
-        let mode = CString::new(mode).map_err(|_| HamLibError {
-            error_code: 0,
-            message: "mode contains an interior null byte",
-        })?;
+    /// Enables or disables split operation, transmitting on `tx_vfo` while receiving on `vfo`.
+    pub fn set_split_vfo(&self, vfo: u32, split: bool, tx_vfo: u32) -> Result<(), HamLibError> {
+        let split: split_t = if split {
+            split_t_RIG_SPLIT_ON
+        } else {
+            split_t_RIG_SPLIT_OFF
+        };
 
+        unsafe { hamlib_result(hamlib_raw::rig_set_split_vfo(self.rig, vfo, split, tx_vfo)) }
+    }
+
+    /// Returns whether split operation is enabled and the transmit VFO.
+    pub fn get_split_vfo(&self, vfo: u32) -> Result<(bool, vfo_t), HamLibError> {
         unsafe {
-            let mode: rmode_t = hamlib_raw::rig_parse_mode(mode.as_ptr());
-            if mode == RIG_MODE_NONE as rmode_t {
-                return Err(HamLibError {
-                    error_code: 0,
-                    message: "unknown hamlib mode",
-                });
-            }
+            let mut split: split_t = split_t_RIG_SPLIT_OFF;
+            let mut tx_vfo: vfo_t = 0;
+
+            hamlib_result(hamlib_raw::rig_get_split_vfo(
+                self.rig,
+                vfo,
+                &mut split,
+                &mut tx_vfo,
+            ))?;
+            Ok((split == split_t_RIG_SPLIT_ON, tx_vfo))
+        }
+    }
+
+    pub fn set_split_freq(&self, vfo: u32, tx_freq: freq_t) -> Result<(), HamLibError> {
+        unsafe { hamlib_result(hamlib_raw::rig_set_split_freq(self.rig, vfo, tx_freq)) }
+    }
+
+    pub fn get_split_freq(&self, vfo: u32) -> Result<freq_t, HamLibError> {
+        unsafe {
+            let mut tx_freq: freq_t = 0.0;
+
+            hamlib_result(hamlib_raw::rig_get_split_freq(self.rig, vfo, &mut tx_freq))?;
+            Ok(tx_freq)
+        }
+    }
+
+    pub fn set_split_mode(&self, vfo: u32, tx_mode: &str) -> Result<(), HamLibError> {
+        let tx_mode = parse_mode(tx_mode)?;
 
-            let width: pbwidth_t = hamlib_raw::rig_passband_normal(self.rig, mode);
-            let ret = hamlib_raw::rig_set_mode(self.rig, vfo, mode, width) as u32;
-            if ret == rig_errcode_e_RIG_OK {
-                Ok(())
+        unsafe {
+            let width: pbwidth_t = hamlib_raw::rig_passband_normal(self.rig, tx_mode);
+            hamlib_result(hamlib_raw::rig_set_split_mode(
+                self.rig, vfo, tx_mode, width,
+            ))
+        }
+    }
+
+    /// Returns the transmit mode name and passband width in Hz.
+    pub fn get_split_mode(&self, vfo: u32) -> Result<(String, i64), HamLibError> {
+        unsafe {
+            let mut tx_mode: rmode_t = RIG_MODE_NONE as rmode_t;
+            let mut width: pbwidth_t = 0;
+
+            hamlib_result(hamlib_raw::rig_get_split_mode(
+                self.rig,
+                vfo,
+                &mut tx_mode,
+                &mut width,
+            ))?;
+            Ok((mode_name(tx_mode), width as i64))
+        }
+    }
+
+    /// Sets the RIT offset in Hz, 0 clears it.
+    pub fn set_rit(&self, vfo: u32, offset: i32) -> Result<(), HamLibError> {
+        unsafe {
+            hamlib_result(hamlib_raw::rig_set_rit(
+                self.rig,
+                vfo,
+                offset as shortfreq_t,
+            ))
+        }
+    }
+
+    pub fn get_rit(&self, vfo: u32) -> Result<i32, HamLibError> {
+        unsafe {
+            let mut offset: shortfreq_t = 0;
+
+            hamlib_result(hamlib_raw::rig_get_rit(self.rig, vfo, &mut offset))?;
+            Ok(offset as i32)
+        }
+    }
+
+    /// Sets the XIT offset in Hz, 0 clears it.
+    pub fn set_xit(&self, vfo: u32, offset: i32) -> Result<(), HamLibError> {
+        unsafe {
+            hamlib_result(hamlib_raw::rig_set_xit(
+                self.rig,
+                vfo,
+                offset as shortfreq_t,
+            ))
+        }
+    }
+
+    pub fn get_xit(&self, vfo: u32) -> Result<i32, HamLibError> {
+        unsafe {
+            let mut offset: shortfreq_t = 0;
+
+            hamlib_result(hamlib_raw::rig_get_xit(self.rig, vfo, &mut offset))?;
+            Ok(offset as i32)
+        }
+    }
+
+    pub fn get_level(&self, vfo: u32, level: RigLevel) -> Result<f32, HamLibError> {
+        unsafe {
+            let mut value = value_t { i: 0 };
+
+            hamlib_result(hamlib_raw::rig_get_level(
+                self.rig,
+                vfo,
+                level.as_hamlib_level(),
+                &mut value,
+            ))?;
+            if level.is_float() {
+                Ok(value.f)
             } else {
-                Err(HamLibError::from_hamlib_error_code(ret))
+                Ok(value.i as f32)
             }
         }
     }
 
-    pub fn set_band_select(&self, band: u32) -> Result<(), HamLibError<'_>> {
+    /// Sets the level value. Integer levels are rounded.
+    pub fn set_level(&self, vfo: u32, level: RigLevel, value: f32) -> Result<(), HamLibError> {
+        let value = if level.is_float() {
+            value_t { f: value }
+        } else {
+            value_t {
+                i: value.round() as i32,
+            }
+        };
+
+        unsafe {
+            hamlib_result(hamlib_raw::rig_set_level(
+                self.rig,
+                vfo,
+                level.as_hamlib_level(),
+                value,
+            ))
+        }
+    }
+
+    pub fn set_func(
+        &self,
+        vfo: u32,
+        function: RigFunction,
+        enabled: bool,
+    ) -> Result<(), HamLibError> {
+        unsafe {
+            hamlib_result(hamlib_raw::rig_set_func(
+                self.rig,
+                vfo,
+                function.as_hamlib_func(),
+                enabled as c_int,
+            ))
+        }
+    }
+
+    pub fn get_func(&self, vfo: u32, function: RigFunction) -> Result<bool, HamLibError> {
+        unsafe {
+            let mut status: c_int = 0;
+
+            hamlib_result(hamlib_raw::rig_get_func(
+                self.rig,
+                vfo,
+                function.as_hamlib_func(),
+                &mut status,
+            ))?;
+            Ok(status != 0)
+        }
+    }
+
+    /// Selects the antenna, with the backend specific antenna option.
+    pub fn set_ant(&self, vfo: u32, antenna: ant_t, option: i32) -> Result<(), HamLibError> {
+        unsafe {
+            hamlib_result(hamlib_raw::rig_set_ant(
+                self.rig,
+                vfo,
+                antenna,
+                value_t { i: option },
+            ))
+        }
+    }
+
+    pub fn get_ant(&self, vfo: u32) -> Result<RigAntennas, HamLibError> {
+        unsafe {
+            let mut option = value_t { i: 0 };
+            let mut antennas = RigAntennas::default();
+
+            hamlib_result(hamlib_raw::rig_get_ant(
+                self.rig,
+                vfo,
+                RIG_ANT_CURR,
+                &mut option,
+                &mut antennas.current,
+                &mut antennas.tx,
+                &mut antennas.rx,
+            ))?;
+            antennas.option = option.i;
+            Ok(antennas)
+        }
+    }
+
+    pub fn set_band_select(&self, band: u32) -> Result<(), HamLibError> {
         unsafe {
             let value = value_t { i: band as i32 };
-            let ret = hamlib_raw::rig_set_parm(self.rig, RIG_PARM_BANDSELECT, value) as u32;
-            if ret == rig_errcode_e_RIG_OK {
-                Ok(())
-            } else {
-                Err(HamLibError::from_hamlib_error_code(ret))
-            }
+            hamlib_result(hamlib_raw::rig_set_parm(
+                self.rig,
+                RIG_PARM_BANDSELECT,
+                value,
+            ))
         }
     }
 
-    pub fn set_band(&self, band: &str) -> Result<(), HamLibError<'_>> {
-        let band = parse_band_select(band).ok_or(HamLibError {
-            error_code: 0,
-            message: "unknown hamlib band",
-        })?;
+    pub fn set_band(&self, band: &str) -> Result<(), HamLibError> {
+        let band = parse_band_select(band).ok_or(HamLibError::InvalidParameter)?;
         self.set_band_select(band)
     }
 
-    pub fn vfo_op(&self, vfo: u32, operation: RigVfoOperation) -> Result<(), HamLibError<'_>> {
+    pub fn vfo_op(&self, vfo: u32, operation: RigVfoOperation) -> Result<(), HamLibError> {
         unsafe {
-            let ret = hamlib_raw::rig_vfo_op(self.rig, vfo, operation.as_hamlib_vfo_op()) as u32;
-            if ret == rig_errcode_e_RIG_OK {
-                Ok(())
-            } else {
-                Err(HamLibError::from_hamlib_error_code(ret))
-            }
+            hamlib_result(hamlib_raw::rig_vfo_op(
+                self.rig,
+                vfo,
+                operation.as_hamlib_vfo_op(),
+            ))
         }
     }
 
-    pub fn get_freq(&self, vfo: u32) -> Result<freq_t, HamLibError<'_>> {
+    pub fn set_ptt(&self, vfo: u32, ptt: RigPtt) -> Result<(), HamLibError> {
+        unsafe { hamlib_result(hamlib_raw::rig_set_ptt(self.rig, vfo, ptt.as_hamlib_ptt())) }
+    }
+
+    pub fn get_ptt(&self, vfo: u32) -> Result<RigPtt, HamLibError> {
+        unsafe {
+            let mut ptt: ptt_t = ptt_t_RIG_PTT_OFF;
+
+            hamlib_result(hamlib_raw::rig_get_ptt(self.rig, vfo, &mut ptt))?;
+            Ok(RigPtt::from_hamlib_ptt(ptt))
+        }
+    }
+
+    pub fn get_freq(&self, vfo: u32) -> Result<freq_t, HamLibError> {
         unsafe {
             let mut freq: freq_t = 0.0;
             let freq_ptr: *mut freq_t = &mut freq;
 
-            let ret = hamlib_raw::rig_get_freq(self.rig, vfo, freq_ptr) as u32;
-            if ret == rig_errcode_e_RIG_OK {
-                return Ok(freq);
-            }
-            return Err(HamLibError::from_hamlib_error_code(ret));
+            hamlib_result(hamlib_raw::rig_get_freq(self.rig, vfo, freq_ptr))?;
+            Ok(freq)
         }
     }
 
-    pub fn get_mode(&self, vfo: u32) -> Result<String, HamLibError<'_>> {
+    /// Returns the mode name and the passband width in Hz.
+    pub fn get_mode(&self, vfo: u32) -> Result<(String, i64), HamLibError> {
         unsafe {
             let mut mode: rmode_t = RIG_MODE_NONE as rmode_t;
             let mut width: pbwidth_t = 0;
 
-            let ret = hamlib_raw::rig_get_mode(self.rig, vfo, &mut mode, &mut width) as u32;
-            if ret == rig_errcode_e_RIG_OK {
-                let mode = CStr::from_ptr(hamlib_raw::rig_strrmode(mode))
-                    .to_string_lossy()
-                    .into_owned();
-                return Ok(mode);
-            }
-            Err(HamLibError::from_hamlib_error_code(ret))
+            hamlib_result(hamlib_raw::rig_get_mode(
+                self.rig, vfo, &mut mode, &mut width,
+            ))?;
+            Ok((mode_name(mode), width as i64))
         }
     }
 }
 
+impl Drop for Rig {
+    fn drop(&mut self) {
+        let _ = self.close();
+        unsafe {
+            hamlib_raw::rig_cleanup(self.rig);
+        }
+    }
+}
+
+fn parse_mode(mode: &str) -> Result<rmode_t, HamLibError> {
+    let mode = CString::new(mode).map_err(|_| HamLibError::InvalidParameter)?;
+    let mode: rmode_t = unsafe { hamlib_raw::rig_parse_mode(mode.as_ptr()) };
+    if mode == RIG_MODE_NONE as rmode_t {
+        return Err(HamLibError::InvalidParameter);
+    }
+    Ok(mode)
+}
+
+fn mode_name(mode: rmode_t) -> String {
+    unsafe {
+        CStr::from_ptr(hamlib_raw::rig_strrmode(mode))
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
 fn parse_band_select(band: &str) -> Option<u32> {
     let normalized = band
         .trim()
@@ -0,0 +1,170 @@
+/*
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License,
+or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful, but
+WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program. If not, see <https://www.gnu.org/licenses/>
+ */
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, Weak};
+
+use tokio::sync::Notify;
+
+/// Sends every frame to all the subscribers.
+///
+/// Each subscriber has its own bounded queue. A slow subscriber loses its oldest
+/// frames instead of slowing down the sender or the other subscribers. Frames are
+/// sent from any thread, the subscribers receive them from async tasks.
+pub struct FrameBroadcaster<T> {
+    subscribers: Mutex<Vec<Weak<SubscriberQueue<T>>>>,
+    capacity: usize,
+}
+
+pub struct FrameSubscriber<T> {
+    queue: Arc<SubscriberQueue<T>>,
+}
+
+struct SubscriberQueue<T> {
+    frames: Mutex<SubscriberFrames<T>>,
+    notify: Notify,
+}
+
+struct SubscriberFrames<T> {
+    frames: VecDeque<T>,
+    closed: bool,
+}
+
+impl<T: Clone> FrameBroadcaster<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            subscribers: Mutex::new(vec![]),
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub fn subscribe(&self) -> FrameSubscriber<T> {
+        let queue = Arc::new(SubscriberQueue {
+            frames: Mutex::new(SubscriberFrames {
+                frames: VecDeque::with_capacity(self.capacity),
+                closed: false,
+            }),
+            notify: Notify::new(),
+        });
+        self.subscribers
+            .lock()
+            .unwrap()
+            .push(Arc::downgrade(&queue));
+        FrameSubscriber { queue }
+    }
+
+    /// Queues the frame for every subscriber. Returns the number of subscribers.
+    pub fn send(&self, frame: T) -> usize {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| {
+            let Some(queue) = subscriber.upgrade() else {
+                return false;
+            };
+            {
+                let mut frames = queue.frames.lock().unwrap();
+                if frames.frames.len() >= self.capacity {
+                    frames.frames.pop_front();
+                }
+                frames.frames.push_back(frame.clone());
+            }
+            queue.notify.notify_one();
+            true
+        });
+        subscribers.len()
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| subscriber.strong_count() > 0);
+        subscribers.len()
+    }
+}
+
+impl<T> Drop for FrameBroadcaster<T> {
+    fn drop(&mut self) {
+        for queue in self.subscribers.lock().unwrap().iter() {
+            if let Some(queue) = queue.upgrade() {
+                queue.frames.lock().unwrap().closed = true;
+                queue.notify.notify_one();
+            }
+        }
+    }
+}
+
+impl<T> FrameSubscriber<T> {
+    /// Waits for the next frame. Returns `None` once the broadcaster is dropped and
+    /// the queued frames are consumed.
+    pub async fn recv(&self) -> Option<T> {
+        loop {
+            {
+                let mut frames = self.queue.frames.lock().unwrap();
+                if let Some(frame) = frames.frames.pop_front() {
+                    return Some(frame);
+                }
+                if frames.closed {
+                    return None;
+                }
+            }
+            self.queue.notify.notified().await;
+        }
+    }
+
+    pub fn try_recv(&self) -> Option<T> {
+        self.queue.frames.lock().unwrap().frames.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FrameBroadcaster;
+
+    #[test]
+    fn every_subscriber_gets_every_frame() {
+        let broadcaster = FrameBroadcaster::new(10);
+        let first = broadcaster.subscribe();
+        let second = broadcaster.subscribe();
+
+        broadcaster.send(1);
+        broadcaster.send(2);
+
+        assert_eq!((first.try_recv(), first.try_recv()), (Some(1), Some(2)));
+        assert_eq!((second.try_recv(), second.try_recv()), (Some(1), Some(2)));
+    }
+
+    #[test]
+    fn full_queue_drops_oldest_frames() {
+        let broadcaster = FrameBroadcaster::new(2);
+        let subscriber = broadcaster.subscribe();
+
+        for frame in 1..=4 {
+            broadcaster.send(frame);
+        }
+
+        assert_eq!(subscriber.try_recv(), Some(3));
+        assert_eq!(subscriber.try_recv(), Some(4));
+        assert_eq!(subscriber.try_recv(), None);
+    }
+
+    #[test]
+    fn dropped_subscribers_are_removed() {
+        let broadcaster = FrameBroadcaster::new(2);
+        let subscriber = broadcaster.subscribe();
+        drop(broadcaster.subscribe());
+
+        assert_eq!(broadcaster.send(1), 1);
+        drop(subscriber);
+        assert_eq!(broadcaster.subscriber_count(), 0);
+    }
+}
@@ -13,6 +13,9 @@ You should have received a copy of the GNU General Public License
 along with this program. If not, see <https://www.gnu.org/licenses/>
  */
 
+pub mod broadcast;
+pub mod resampler;
+
 use std::sync::Arc;
 
 use bytes::Bytes;
@@ -22,7 +25,10 @@ pub struct AudioFrame {
     pub(crate) data: Arc<Vec<f32>>,
 }
 
+#[derive(Clone)]
 pub struct AudioEncodedFrame {
     pub bytes: Bytes,
     pub duration: Duration,
+    // Silent frames not sent before this one, see the encoder DTX
+    pub skipped_frames: u16,
 }
@@ -0,0 +1,97 @@
+/*
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License,
+or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful, but
+WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program. If not, see <https://www.gnu.org/licenses/>
+ */
+
+/// Streaming mono resampler using linear interpolation.
+///
+/// Good enough for the transceiver audio, which is band limited to a few kHz well
+/// below the Nyquist frequency of the usual sound card rates.
+pub struct LinearResampler {
+    // Input samples consumed per output sample
+    step: f64,
+    // Position of the next output sample, relative to the last input sample
+    position: f64,
+    last_sample: f32,
+}
+
+impl LinearResampler {
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        Self {
+            step: input_rate as f64 / output_rate as f64,
+            position: 1.0,
+            last_sample: 0.0,
+        }
+    }
+
+    pub fn is_passthrough(&self) -> bool {
+        self.step == 1.0
+    }
+
+    /// Resamples the input samples and appends them to the output.
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        if self.is_passthrough() {
+            output.extend_from_slice(input);
+            return;
+        }
+        for &sample in input {
+            // Output samples between the last input sample and this one
+            while self.position <= 1.0 {
+                let position = self.position as f32;
+                output.push(self.last_sample + (sample - self.last_sample) * position);
+                self.position += self.step;
+            }
+            self.position -= 1.0;
+            self.last_sample = sample;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LinearResampler;
+
+    #[test]
+    fn same_rate_is_passthrough() {
+        let mut resampler = LinearResampler::new(48000, 48000);
+        let mut output = vec![];
+
+        resampler.process(&[0.1, 0.2, 0.3], &mut output);
+
+        assert_eq!(output, vec![0.1, 0.2, 0.3]);
+    }
+
+    #[test]
+    fn output_length_follows_rate_ratio() {
+        let mut resampler = LinearResampler::new(44100, 48000);
+        let mut output = vec![];
+
+        // One second of audio, in chunks like a sound card callback
+        for _ in 0..100 {
+            resampler.process(&[0.5; 441], &mut output);
+        }
+
+        assert!((output.len() as i64 - 48000).abs() <= 1);
+        assert!(output.iter().all(|&sample| sample == 0.5));
+    }
+
+    #[test]
+    fn downsampling_keeps_every_other_sample() {
+        let mut resampler = LinearResampler::new(96000, 48000);
+        let mut output = vec![];
+
+        resampler.process(&[0.0, 1.0, 2.0, 3.0, 4.0], &mut output);
+
+        assert_eq!(output, vec![0.0, 2.0, 4.0]);
+    }
+}
@@ -31,6 +31,12 @@ pub struct Configuration {
     pub lock_file: PathBuf,
     pub signaling_server: SignalingServer,
     pub transceiver: Transceiver,
+    #[serde(default)]
+    pub audio: Audio,
+    #[serde(default)]
+    pub sessions: Sessions,
+    #[serde(default)]
+    pub webrtc: Webrtc,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -58,14 +64,209 @@ pub struct Transceiver {
         default = "default_state_polling_interval_ms"
     )]
     pub state_polling_interval_ms: u64,
+    #[serde(
+        rename = "meterPollingInterval",
+        default = "default_meter_polling_interval_ms"
+    )]
+    pub meter_polling_interval_ms: u64,
+    #[serde(
+        rename = "maxTransmitTimeSeconds",
+        default = "default_max_transmit_time_seconds"
+    )]
+    pub max_transmit_time_seconds: u64,
+    #[serde(
+        rename = "reconnectRetryDelaySeconds",
+        default = "default_reconnect_retry_delay_seconds"
+    )]
+    pub reconnect_retry_delay_seconds: Vec<u64>,
     #[serde(default)]
     pub port: HashMap<String, String>,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+pub struct Audio {
+    #[serde(rename = "inputDevice", default)]
+    pub input_device: Option<String>,
+    #[serde(rename = "inputChannel", default)]
+    pub input_channel: AudioInputChannel,
+    #[serde(
+        rename = "captureStopDelaySeconds",
+        default = "default_capture_stop_delay_seconds"
+    )]
+    pub capture_stop_delay_seconds: u64,
+    #[serde(rename = "outputDevice", default)]
+    pub output_device: Option<String>,
+    #[serde(
+        rename = "dtxSquelchLevelDbfs",
+        default = "default_dtx_squelch_level_dbfs"
+    )]
+    pub dtx_squelch_level_dbfs: i32,
+    #[serde(default)]
+    pub encoder: AudioEncoderProfile,
+}
+
+/// Opus encoder settings of the audio sent to the clients. The operator may request
+/// another profile at runtime.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioEncoderProfile {
+    #[serde(default)]
+    pub application: AudioEncoderApplication,
+    // Bits per second, chosen by the encoder when not set
+    #[serde(default)]
+    pub bitrate: Option<i32>,
+    #[serde(
+        rename = "frameDurationMs",
+        default = "default_encoder_frame_duration_ms"
+    )]
+    pub frame_duration_ms: u32,
+    #[serde(rename = "inbandFec", default)]
+    pub inband_fec: bool,
+    #[serde(default)]
+    pub dtx: bool,
+    #[serde(rename = "packetLossPercent", default)]
+    pub packet_loss_percent: u8,
+}
+
+impl Default for AudioEncoderProfile {
+    fn default() -> Self {
+        Self {
+            application: AudioEncoderApplication::default(),
+            bitrate: None,
+            frame_duration_ms: default_encoder_frame_duration_ms(),
+            inband_fec: false,
+            dtx: false,
+            packet_loss_percent: 0,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AudioEncoderApplication {
+    #[default]
+    Voip,
+    Audio,
+    LowDelay,
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Self {
+            input_device: None,
+            input_channel: AudioInputChannel::default(),
+            capture_stop_delay_seconds: default_capture_stop_delay_seconds(),
+            output_device: None,
+            dtx_squelch_level_dbfs: default_dtx_squelch_level_dbfs(),
+            encoder: AudioEncoderProfile::default(),
+        }
+    }
+}
+
+/// Channel of the input device sent to the clients. `Mix` averages all the channels.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AudioInputChannel {
+    Left,
+    Right,
+    #[default]
+    Mix,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Sessions {
+    #[serde(
+        rename = "operatorIdleTimeoutSeconds",
+        default = "default_operator_idle_timeout_seconds"
+    )]
+    pub operator_idle_timeout_seconds: u64,
+    #[serde(rename = "maxSessions", default = "default_max_sessions")]
+    pub max_sessions: usize,
+}
+
+impl Default for Sessions {
+    fn default() -> Self {
+        Self {
+            operator_idle_timeout_seconds: default_operator_idle_timeout_seconds(),
+            max_sessions: default_max_sessions(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Webrtc {
+    #[serde(rename = "iceServers", default = "default_ice_servers")]
+    pub ice_servers: Vec<IceServer>,
+    #[serde(rename = "iceTransportPolicy", default)]
+    pub ice_transport_policy: IceTransportPolicy,
+}
+
+impl Default for Webrtc {
+    fn default() -> Self {
+        Self {
+            ice_servers: default_ice_servers(),
+            ice_transport_policy: IceTransportPolicy::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct IceServer {
+    pub urls: Vec<String>,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub credential: String,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+pub enum IceTransportPolicy {
+    #[default]
+    All,
+    Relay,
+}
+
+fn default_ice_servers() -> Vec<IceServer> {
+    vec![IceServer {
+        urls: vec!["stun:stun.l.google.com:19302".to_string()],
+        username: String::new(),
+        credential: String::new(),
+    }]
+}
+
+fn default_capture_stop_delay_seconds() -> u64 {
+    10
+}
+
+fn default_dtx_squelch_level_dbfs() -> i32 {
+    -60
+}
+
+fn default_encoder_frame_duration_ms() -> u32 {
+    20
+}
+
+fn default_max_sessions() -> usize {
+    8
+}
+
+fn default_operator_idle_timeout_seconds() -> u64 {
+    300
+}
+
 fn default_state_polling_interval_ms() -> u64 {
     1000
 }
 
+fn default_meter_polling_interval_ms() -> u64 {
+    200
+}
+
+fn default_max_transmit_time_seconds() -> u64 {
+    180
+}
+
+fn default_reconnect_retry_delay_seconds() -> Vec<u64> {
+    vec![1, 2, 5, 10, 30]
+}
+
 fn default_connection_retry_delay_seconds() -> Vec<u64> {
     vec![1, 1, 3, 5, 15, 30, 60]
 }
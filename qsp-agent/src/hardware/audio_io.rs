@@ -12,151 +12,949 @@ General Public License for more details.
 You should have received a copy of the GNU General Public License
 along with this program. If not, see <https://www.gnu.org/licenses/>
  */
-use std::sync::Arc;
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Instant;
 use tokio::time::Duration;
 
+use crate::audio::broadcast::{FrameBroadcaster, FrameSubscriber};
+use crate::audio::resampler::LinearResampler;
 use crate::audio::{AudioEncodedFrame, AudioFrame};
+use crate::configuration::{
+    Audio, AudioEncoderApplication, AudioEncoderProfile, AudioInputChannel,
+};
 use bytes::Bytes;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::Stream;
+use cpal::{FromSample, Sample, SizedSample, Stream};
 use cpal::{SampleFormat, SupportedStreamConfig, SupportedStreamConfigRange};
-use flume::Receiver;
-use tracing::{debug, error, info};
+use flume::{Receiver, RecvTimeoutError, Sender};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::{debug, error, info, warn};
+
+const ENCODER_SAMPLE_RATE: u32 = 48000;
+// Encoded frames waiting for each subscriber, the oldest are dropped beyond
+const AUDIO_SUBSCRIBER_QUEUE_FRAMES: usize = 5;
+// Recommended maximum size of an Opus packet
+const OPUS_MAX_PACKET_BYTES: usize = 4000;
+// Like the Opus DTX, send a frame every 400 ms while the DTX squelch is closed
+const DTX_KEEPALIVE_INTERVAL_MS: u32 = 400;
+// Frame durations supported by the Opus encoder
+const ENCODER_FRAME_DURATIONS_MS: [u32; 4] = [10, 20, 40, 60];
+// Bitrate range supported by the Opus encoder
+const ENCODER_BITRATE_RANGE: RangeInclusive<i32> = 500..=512000;
+// Device sample formats by order of preference
+const DEVICE_SAMPLE_FORMATS: [SampleFormat; 3] =
+    [SampleFormat::F32, SampleFormat::I16, SampleFormat::U16];
+const PLAYBACK_SAMPLE_RATE: u32 = 48000;
+// Opus packets carry at most 120 ms of audio.
+const OPUS_MAX_FRAME_SAMPLES: usize = 5760;
+// Keep at most 200 ms of decoded audio waiting for the output device.
+const PLAYBACK_MAX_BUFFERED_SAMPLES: usize = 9600;
+// Delays before reopening a failed audio device, doubled at each failure
+const AUDIO_REOPEN_MIN_DELAY: Duration = Duration::from_secs(1);
+const AUDIO_REOPEN_MAX_DELAY: Duration = Duration::from_secs(30);
+const AUDIO_STOP_CHECK_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(thiserror::Error, Debug, Eq, PartialEq)]
+pub enum AudioEncoderProfileError {
+    #[error("bitrate {0} out of the 500-512000 bits/s range")]
+    InvalidBitrate(i32),
+    #[error("frame duration {0} ms is not 10, 20, 40 or 60 ms")]
+    InvalidFrameDuration(u32),
+    #[error("packet loss {0}% out of the 0-100% range")]
+    InvalidPacketLoss(u8),
+}
+
+#[derive(thiserror::Error, Debug, Clone, Eq, PartialEq)]
+pub enum AudioError {
+    #[error("audio device '{0}' not found")]
+    DeviceNotFound(String),
+    #[error("no supported audio config on device '{0}'")]
+    UnsupportedDevice(String),
+    #[error("failed to open the audio stream: {0}")]
+    StreamOpenFailed(String),
+    #[error("audio stream failed: {0}")]
+    StreamFailed(String),
+    #[error("audio encoder failed: {0}")]
+    EncoderFailed(String),
+    #[error("audio decoder failed: {0}")]
+    DecoderFailed(String),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AudioDirection {
+    Input,
+    Output,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AudioEvent {
+    /// The device stream is running.
+    Started {
+        direction: AudioDirection,
+        device_name: String,
+    },
+    /// The device stream failed or could not be opened, it is reopened after the delay.
+    Failed {
+        direction: AudioDirection,
+        error: AudioError,
+        retry_in: Duration,
+    },
+    /// The device stream is closed, nobody uses it.
+    Stopped { direction: AudioDirection },
+    /// A frame could not be encoded or decoded, the stream goes on.
+    FrameDropped {
+        direction: AudioDirection,
+        error: AudioError,
+    },
+}
+
+type AudioEventSenders = Arc<Mutex<Vec<UnboundedSender<AudioEvent>>>>;
 
 pub struct AudioSessionManager {
+    configuration: Audio,
     session: Option<AudioSession>,
+    playback_session: Option<AudioPlaybackSession>,
+    operator_present: Arc<AtomicBool>,
+    encoder_profile: AudioEncoderProfile,
+    encoder_profile_senders: Vec<UnboundedSender<AudioEncoderProfile>>,
+    event_senders: AudioEventSenders,
 }
 
 impl AudioSessionManager {
-    pub fn new() -> Self {
-        Self { session: None }
+    pub fn new(configuration: Audio) -> Self {
+        let encoder_profile = match check_encoder_profile(&configuration.encoder) {
+            Ok(()) => configuration.encoder,
+            Err(e) => {
+                error!(
+                    "Invalid audio encoder configuration, use the defaults: {}",
+                    e
+                );
+                AudioEncoderProfile::default()
+            }
+        };
+        Self {
+            configuration,
+            session: None,
+            playback_session: None,
+            operator_present: Arc::new(AtomicBool::new(false)),
+            encoder_profile,
+            encoder_profile_senders: vec![],
+            event_senders: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    /// Subscribes to the encoded audio. Every subscriber gets all the frames.
+    pub fn subscribe_audio(&mut self) -> FrameSubscriber<AudioEncodedFrame> {
+        let audio_session = self.session.get_or_insert_with(|| {
+            AudioSession::new(
+                self.configuration.clone(),
+                self.encoder_profile,
+                self.event_senders.clone(),
+            )
+        });
+
+        audio_session.encoded_frames.subscribe()
+    }
+
+    pub fn encoder_profile(&self) -> AudioEncoderProfile {
+        self.encoder_profile
     }
 
-    pub fn get_audio_receiver(&mut self) -> Receiver<AudioEncodedFrame> {
-        if self.session.is_none() {
-            self.session = Some(AudioSession::new())
+    /// Changes the encoder settings of the audio sent to all the clients.
+    pub fn set_encoder_profile(
+        &mut self,
+        profile: AudioEncoderProfile,
+    ) -> Result<(), AudioEncoderProfileError> {
+        check_encoder_profile(&profile)?;
+        if profile == self.encoder_profile {
+            return Ok(());
         }
-        let audio_session = &self.session.as_ref().unwrap();
+        self.encoder_profile = profile;
+        if let Some(session) = &self.session {
+            let _ = session.profile_sender.send(profile);
+        }
+        self.encoder_profile_senders
+            .retain(|sender| sender.send(profile).is_ok());
+        Ok(())
+    }
+
+    pub fn add_encoder_profile_receiver(&mut self) -> UnboundedReceiver<AudioEncoderProfile> {
+        let (sender, receiver) = unbounded_channel();
+        self.encoder_profile_senders.push(sender);
+        receiver
+    }
+
+    /// Receives the state changes and the errors of the audio devices.
+    pub fn add_event_receiver(&self) -> UnboundedReceiver<AudioEvent> {
+        let (sender, receiver) = unbounded_channel();
+        self.event_senders.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Only the operator transmits, the output device is open while a session holds
+    /// the control.
+    pub fn set_operator_present(&self, present: bool) {
+        self.operator_present.store(present, Ordering::Relaxed);
+    }
+
+    pub fn get_audio_playback_sender(&mut self) -> Sender<AudioEncodedFrame> {
+        let playback_session = self.playback_session.get_or_insert_with(|| {
+            AudioPlaybackSession::new(
+                self.configuration.output_device.clone(),
+                Duration::from_secs(self.configuration.capture_stop_delay_seconds),
+                self.operator_present.clone(),
+                self.event_senders.clone(),
+            )
+        });
 
-        audio_session.encoded_receiver.clone()
+        playback_session.encoded_sender.clone()
     }
 }
 
-#[derive(Clone)]
+/// Captures the transceiver audio and encodes it for all the subscribers. The input
+/// device is only open while there are subscribers. It is reopened when it fails,
+/// the subscriptions stay open meanwhile.
 pub struct AudioSession {
-    _stream: Arc<Stream>,
-    pub encoded_receiver: Receiver<AudioEncodedFrame>,
+    encoded_frames: Arc<FrameBroadcaster<AudioEncodedFrame>>,
+    profile_sender: Sender<AudioEncoderProfile>,
+    stop: Arc<AtomicBool>,
 }
 
 impl AudioSession {
-    pub fn new() -> Self {
-        let (sender, frame_receiver) = flume::bounded::<AudioFrame>(3);
-        let (encoded_sender, encoded_receiver) = flume::bounded::<AudioEncodedFrame>(3);
+    pub fn new(
+        configuration: Audio,
+        profile: AudioEncoderProfile,
+        event_senders: AudioEventSenders,
+    ) -> Self {
+        let (sender, frame_receiver) = flume::bounded::<AudioFrame>(10);
+        // Sample buffers handed back by the encoder to the capture callback
+        let (spare_buffer_sender, spare_buffer_receiver) = flume::bounded::<Vec<f32>>(10);
+        let (profile_sender, profile_receiver) = flume::unbounded::<AudioEncoderProfile>();
+        let encoded_frames = Arc::new(FrameBroadcaster::new(AUDIO_SUBSCRIBER_QUEUE_FRAMES));
+        let stop = Arc::new(AtomicBool::new(false));
 
+        let encoder_event_senders = event_senders.clone();
+        let dtx_squelch_level = 10f32.powf(configuration.dtx_squelch_level_dbfs as f32 / 20.0);
         thread::spawn(move || {
-            // We just handle 48khz, to handle other sample rates like 44.1khz you need to use a resampler.
-            let mut encoder =
-                opus::Encoder::new(48000, opus::Channels::Mono, opus::Application::Voip).unwrap();
-            debug!("Start audio encoder");
-            loop {
-                let AudioFrame { data } = frame_receiver.recv().unwrap();
-
-                let sample_count = data.len() as u64;
-                // sample duration
-                let duration = Duration::from_millis(sample_count * 1000 / 48000);
-                let encoded = encoder
-                    .encode_vec_float(&data, 1024)
-                    .expect("Failed to encode");
-                let bytes = Bytes::from(encoded);
-
-                encoded_sender
-                    .send(AudioEncodedFrame { bytes, duration })
-                    .unwrap();
-            }
+            AudioSession::run_encoder(
+                profile,
+                dtx_squelch_level,
+                frame_receiver,
+                spare_buffer_sender,
+                profile_receiver,
+                encoded_frames.clone(),
+                encoder_event_senders,
+            )
         });
 
-        let host = cpal::default_host();
+        // The capture runs while sessions subscribe to the audio, and a little longer
+        // so a reconnecting client doesn't reopen the device
+        let capture_stop_delay = Duration::from_secs(configuration.capture_stop_delay_seconds);
+        let subscribed_frames = encoded_frames.clone();
+        let mut unused_since: Option<Instant> = None;
+        let input_stop = stop.clone();
+        thread::spawn(move || {
+            supervise_stream(
+                AudioDirection::Input,
+                |error_sender| {
+                    AudioSession::open_input_stream(
+                        &configuration,
+                        sender.clone(),
+                        spare_buffer_receiver.clone(),
+                        error_sender,
+                    )
+                },
+                || {
+                    if subscribed_frames.subscriber_count() > 0 {
+                        unused_since = None;
+                        return true;
+                    }
+                    unused_since.get_or_insert_with(Instant::now).elapsed() < capture_stop_delay
+                },
+                event_senders,
+                input_stop,
+            )
+        });
 
-        // Set up the input device and stream with the default input config.
-        let device = host
-            .default_input_device()
-            .expect("failed to find input device");
+        Self {
+            encoded_frames,
+            profile_sender,
+            stop,
+        }
+    }
 
-        info!(
-            "Audio input device: {}",
-            device.description().unwrap().name()
-        );
+    fn run_encoder(
+        profile: AudioEncoderProfile,
+        dtx_squelch_level: f32,
+        frame_receiver: Receiver<AudioFrame>,
+        spare_buffer_sender: Sender<Vec<f32>>,
+        profile_receiver: Receiver<AudioEncoderProfile>,
+        encoded_frames: Arc<FrameBroadcaster<AudioEncodedFrame>>,
+        event_senders: AudioEventSenders,
+    ) {
+        let mut profile = profile;
+        let Some(mut encoder) = AudioSession::create_encoder_with_retry(
+            &mut profile,
+            &frame_receiver,
+            &profile_receiver,
+            &event_senders,
+        ) else {
+            debug!("Audio capture ended before the encoder was created");
+            return;
+        };
+        let mut buffer: Vec<f32> = Vec::new();
+        let mut silent_frames = 0usize;
+        let mut skipped_frames = 0u16;
+        debug!("Start audio encoder");
+        while let Ok(AudioFrame { data }) = frame_receiver.recv() {
+            // Apply the last requested profile between two frames
+            if let Some(requested_profile) = profile_receiver.try_iter().last() {
+                match AudioSession::create_encoder(&requested_profile) {
+                    Ok(requested_encoder) => {
+                        info!("Audio encoder profile changed: {:?}", requested_profile);
+                        encoder = requested_encoder;
+                        profile = requested_profile;
+                    }
+                    Err(e) => error!("Failed to apply audio encoder profile: {}", e),
+                }
+            }
+
+            buffer.extend_from_slice(&data);
+            if let Ok(mut data) = Arc::try_unwrap(data) {
+                data.clear();
+                let _ = spare_buffer_sender.try_send(data);
+            }
+            let frame_samples =
+                profile.frame_duration_ms as usize * ENCODER_SAMPLE_RATE as usize / 1000;
+            while buffer.len() >= frame_samples {
+                let frame: Vec<f32> = buffer.drain(..frame_samples).collect();
+
+                // Discontinuous transmission: a squelch on the frame peak level, not the
+                // Opus DTX. While closed, only send a frame from time to time so the
+                // client keeps the stream alive
+                if profile.dtx && frame.iter().all(|sample| sample.abs() < dtx_squelch_level) {
+                    silent_frames += 1;
+                    let keepalive_frames =
+                        (DTX_KEEPALIVE_INTERVAL_MS / profile.frame_duration_ms) as usize;
+                    if silent_frames % keepalive_frames != 1 {
+                        skipped_frames = skipped_frames.saturating_add(1);
+                        continue;
+                    }
+                } else {
+                    silent_frames = 0;
+                }
+
+                let encoded = match encoder.encode_vec_float(&frame, OPUS_MAX_PACKET_BYTES) {
+                    Ok(encoded) => encoded,
+                    Err(e) => {
+                        send_audio_event(
+                            &event_senders,
+                            AudioEvent::FrameDropped {
+                                direction: AudioDirection::Input,
+                                error: AudioError::EncoderFailed(e.to_string()),
+                            },
+                        );
+                        skipped_frames = skipped_frames.saturating_add(1);
+                        continue;
+                    }
+                };
+
+                let frame = AudioEncodedFrame {
+                    bytes: Bytes::from(encoded),
+                    duration: Duration::from_millis(profile.frame_duration_ms as u64),
+                    skipped_frames,
+                };
+                encoded_frames.send(frame);
+                skipped_frames = 0;
+            }
+        }
+        debug!("End audio encoder");
+    }
+
+    /// Creates the encoder, retrying with a growing delay like the devices. The captured
+    /// frames are dropped meanwhile, and a newly requested profile is used for the next
+    /// attempt. Gives up when the capture ends.
+    fn create_encoder_with_retry(
+        profile: &mut AudioEncoderProfile,
+        frame_receiver: &Receiver<AudioFrame>,
+        profile_receiver: &Receiver<AudioEncoderProfile>,
+        event_senders: &AudioEventSenders,
+    ) -> Option<opus::Encoder> {
+        let mut retry_delay = AUDIO_REOPEN_MIN_DELAY;
+        loop {
+            if let Some(requested_profile) = profile_receiver.try_iter().last() {
+                *profile = requested_profile;
+            }
+            let error = match AudioSession::create_encoder(profile) {
+                Ok(encoder) => return Some(encoder),
+                Err(e) => AudioError::EncoderFailed(e.to_string()),
+            };
+            send_audio_event(
+                event_senders,
+                AudioEvent::Failed {
+                    direction: AudioDirection::Input,
+                    error,
+                    retry_in: retry_delay,
+                },
+            );
+            let retry_at = Instant::now() + retry_delay;
+            while Instant::now() < retry_at {
+                match frame_receiver.recv_timeout(AUDIO_STOP_CHECK_INTERVAL) {
+                    Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => return None,
+                }
+            }
+            retry_delay = (retry_delay * 2).min(AUDIO_REOPEN_MAX_DELAY);
+        }
+    }
+
+    fn create_encoder(profile: &AudioEncoderProfile) -> Result<opus::Encoder, opus::Error> {
+        let application = match profile.application {
+            AudioEncoderApplication::Voip => opus::Application::Voip,
+            AudioEncoderApplication::Audio => opus::Application::Audio,
+            AudioEncoderApplication::LowDelay => opus::Application::LowDelay,
+        };
+        let mut encoder =
+            opus::Encoder::new(ENCODER_SAMPLE_RATE, opus::Channels::Mono, application)?;
+        encoder.set_bitrate(
+            profile
+                .bitrate
+                .map_or(opus::Bitrate::Auto, opus::Bitrate::Bits),
+        )?;
+        encoder.set_inband_fec(profile.inband_fec)?;
+        encoder.set_packet_loss_perc(profile.packet_loss_percent as i32)?;
+        Ok(encoder)
+    }
+
+    fn open_input_stream(
+        configuration: &Audio,
+        sender: Sender<AudioFrame>,
+        spare_buffers: Receiver<Vec<f32>>,
+        error_sender: Sender<cpal::StreamError>,
+    ) -> Result<(Stream, String), AudioError> {
+        let host = cpal::default_host();
+        let device_name = configuration.input_device.as_deref();
+
+        let device = match device_name {
+            Some(device_name) => match host.input_devices() {
+                Ok(devices) => find_device(devices, device_name),
+                Err(e) => {
+                    error!("Error listing audio input devices: {}", e);
+                    None
+                }
+            },
+            None => host.default_input_device(),
+        }
+        .ok_or_else(|| AudioError::DeviceNotFound(device_name.unwrap_or("default").to_string()))?;
+        let device_name = device_label(&device);
 
         let input_configs = match device.supported_input_configs() {
             Ok(f) => f.collect(),
             Err(e) => {
-                println!("    Error getting supported input configs: {:?}", e);
+                error!("Error getting supported input configs: {:?}", e);
                 Vec::new()
             }
         };
-        let config = AudioSession::find_audio_config(input_configs).unwrap();
+        let config = find_audio_config(input_configs, ENCODER_SAMPLE_RATE)
+            .ok_or_else(|| AudioError::UnsupportedDevice(device_name.clone()))?;
+
+        debug!("Audio input config: {:?}", config);
+
+        let input_channel = configuration.input_channel;
+        if input_channel == AudioInputChannel::Right && config.channels() < 2 {
+            warn!("Audio input device is mono, use its only channel instead of the right one");
+        }
+
+        let stream = match config.sample_format() {
+            SampleFormat::I16 => AudioSession::build_input_stream::<i16>(
+                &device,
+                &config,
+                input_channel,
+                sender,
+                spare_buffers,
+                error_sender,
+            ),
+            SampleFormat::U16 => AudioSession::build_input_stream::<u16>(
+                &device,
+                &config,
+                input_channel,
+                sender,
+                spare_buffers,
+                error_sender,
+            ),
+            _ => AudioSession::build_input_stream::<f32>(
+                &device,
+                &config,
+                input_channel,
+                sender,
+                spare_buffers,
+                error_sender,
+            ),
+        }
+        .map_err(|e| AudioError::StreamOpenFailed(e.to_string()))?;
+
+        stream
+            .play()
+            .map_err(|e| AudioError::StreamOpenFailed(e.to_string()))?;
+        Ok((stream, device_name))
+    }
 
-        debug!("Audio default input config: {:?}", config);
+    /// Builds the capture stream. The samples are converted to f32, reduced to the
+    /// selected channel and resampled to 48khz before being sent to the encoder.
+    fn build_input_stream<T>(
+        device: &cpal::Device,
+        config: &SupportedStreamConfig,
+        input_channel: AudioInputChannel,
+        sender: Sender<AudioFrame>,
+        spare_buffers: Receiver<Vec<f32>>,
+        error_sender: Sender<cpal::StreamError>,
+    ) -> Result<Stream, cpal::BuildStreamError>
+    where
+        T: SizedSample,
+        f32: FromSample<T>,
+    {
+        let channels = config.channels() as usize;
+        let mut resampler = LinearResampler::new(config.sample_rate(), ENCODER_SAMPLE_RATE);
+        let mut mono: Vec<f32> = Vec::new();
 
         let err_fn = move |err| {
-            eprintln!("an error occurred on stream: {}", err);
+            let _ = error_sender.send(err);
         };
 
         let config = config.config();
-        // until it is 960
-        let mut buffer: Vec<f32> = Vec::new();
+        device.build_input_stream(
+            config.into(),
+            move |data: &[T], _| {
+                mono.clear();
+                mono.extend(
+                    data.chunks(channels)
+                        .map(|frame| AudioSession::select_channel(frame, input_channel)),
+                );
+                // Reuse a buffer the encoder is done with, the callback only allocates
+                // until enough of them circulate
+                let mut resampled = spare_buffers.try_recv().unwrap_or_default();
+                resampler.process(&mono, &mut resampled);
+                // Never block the audio callback, the samples are dropped if the encoder lags
+                let _ = sender.try_send(AudioFrame {
+                    data: Arc::new(resampled),
+                });
+            },
+            err_fn,
+            None,
+        )
+    }
 
-        // assume cpal::SampleFormat::F32
-        let stream = device
-            .build_input_stream(
-                config.into(),
-                move |data: &[f32], _| {
-                    for &sample in data {
-                        buffer.push(sample.clone());
-                        if buffer.len() == 960 {
-                            sender
-                                .send(AudioFrame {
-                                    data: Arc::new(buffer.to_owned()),
-                                })
-                                .expect("Failed to send raw frame to the encoder");
-                            // Create a new vec
-                            buffer.clear();
-                        }
+    fn select_channel<T>(frame: &[T], input_channel: AudioInputChannel) -> f32
+    where
+        T: SizedSample,
+        f32: FromSample<T>,
+    {
+        match input_channel {
+            AudioInputChannel::Left => frame[0].to_sample(),
+            AudioInputChannel::Right => frame.get(1).unwrap_or(&frame[0]).to_sample(),
+            AudioInputChannel::Mix => {
+                frame
+                    .iter()
+                    .map(|sample| sample.to_sample::<f32>())
+                    .sum::<f32>()
+                    / frame.len() as f32
+            }
+        }
+    }
+}
+
+impl Drop for AudioSession {
+    fn drop(&mut self) {
+        // The supervisor closes the input stream, the encoder ends with it
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Decodes the Opus frames received from the clients and plays them on the
+/// output device wired to the transceiver audio input. The output device is
+/// only open while a session is the operator. It is reopened when it fails.
+pub struct AudioPlaybackSession {
+    pub encoded_sender: Sender<AudioEncodedFrame>,
+    stop: Arc<AtomicBool>,
+}
+
+impl AudioPlaybackSession {
+    pub fn new(
+        device_name: Option<String>,
+        stop_delay: Duration,
+        operator_present: Arc<AtomicBool>,
+        event_senders: AudioEventSenders,
+    ) -> Self {
+        let samples = Arc::new(Mutex::new(VecDeque::<f32>::with_capacity(
+            PLAYBACK_MAX_BUFFERED_SAMPLES,
+        )));
+        let (encoded_sender, encoded_receiver) = flume::bounded::<AudioEncodedFrame>(10);
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let decoded_samples = Arc::clone(&samples);
+        let decoder_event_senders = event_senders.clone();
+        thread::spawn(move || {
+            AudioPlaybackSession::run_decoder(
+                encoded_receiver,
+                decoded_samples,
+                decoder_event_senders,
+            )
+        });
+
+        // Like the capture, the playback goes on a little after the operator leaves so
+        // a handover doesn't reopen the device
+        let mut unused_since: Option<Instant> = None;
+        let output_stop = stop.clone();
+        thread::spawn(move || {
+            supervise_stream(
+                AudioDirection::Output,
+                |error_sender| {
+                    AudioPlaybackSession::open_output_stream(
+                        device_name.as_deref(),
+                        samples.clone(),
+                        error_sender,
+                    )
+                },
+                || {
+                    if operator_present.load(Ordering::Relaxed) {
+                        unused_since = None;
+                        return true;
                     }
+                    unused_since.get_or_insert_with(Instant::now).elapsed() < stop_delay
                 },
-                err_fn,
-                None,
+                event_senders,
+                output_stop,
             )
-            .unwrap();
-
-        stream.play().unwrap();
-        //self.stream = Some(Rc::new(stream));
-        //encoded_receiver
-        let s = Self {
-            _stream: Arc::new(stream),
-            encoded_receiver,
+        });
+
+        Self {
+            encoded_sender,
+            stop,
+        }
+    }
+
+    fn run_decoder(
+        encoded_receiver: Receiver<AudioEncodedFrame>,
+        samples: Arc<Mutex<VecDeque<f32>>>,
+        event_senders: AudioEventSenders,
+    ) {
+        let mut decoder = match opus::Decoder::new(PLAYBACK_SAMPLE_RATE, opus::Channels::Mono) {
+            Ok(decoder) => decoder,
+            Err(e) => {
+                send_audio_event(
+                    &event_senders,
+                    AudioEvent::FrameDropped {
+                        direction: AudioDirection::Output,
+                        error: AudioError::DecoderFailed(e.to_string()),
+                    },
+                );
+                return;
+            }
+        };
+        let mut pcm = vec![0f32; OPUS_MAX_FRAME_SAMPLES];
+        debug!("Start audio decoder");
+        while let Ok(AudioEncodedFrame { bytes, .. }) = encoded_receiver.recv() {
+            match decoder.decode_float(&bytes, &mut pcm, false) {
+                Ok(sample_count) => {
+                    let mut samples = samples.lock().unwrap();
+                    samples.extend(&pcm[..sample_count]);
+                    // Drop the oldest samples when the client sends faster than we play
+                    let overflow = samples.len().saturating_sub(PLAYBACK_MAX_BUFFERED_SAMPLES);
+                    samples.drain(..overflow);
+                }
+                Err(e) => send_audio_event(
+                    &event_senders,
+                    AudioEvent::FrameDropped {
+                        direction: AudioDirection::Output,
+                        error: AudioError::DecoderFailed(e.to_string()),
+                    },
+                ),
+            }
+        }
+        debug!("End audio decoder");
+    }
+
+    fn open_output_stream(
+        device_name: Option<&str>,
+        samples: Arc<Mutex<VecDeque<f32>>>,
+        error_sender: Sender<cpal::StreamError>,
+    ) -> Result<(Stream, String), AudioError> {
+        let host = cpal::default_host();
+
+        let device = match device_name {
+            Some(device_name) => AudioPlaybackSession::find_output_device(&host, device_name),
+            None => host.default_output_device(),
+        }
+        .ok_or_else(|| AudioError::DeviceNotFound(device_name.unwrap_or("default").to_string()))?;
+        let device_name = device_label(&device);
+
+        let output_configs = match device.supported_output_configs() {
+            Ok(f) => f.collect(),
+            Err(e) => {
+                error!("Error getting supported output configs: {:?}", e);
+                Vec::new()
+            }
+        };
+        let config = find_audio_config(output_configs, PLAYBACK_SAMPLE_RATE)
+            .ok_or_else(|| AudioError::UnsupportedDevice(device_name.clone()))?;
+
+        debug!("Audio output config: {:?}", config);
+
+        let stream = match config.sample_format() {
+            SampleFormat::I16 => AudioPlaybackSession::build_output_stream::<i16>(
+                &device,
+                &config,
+                samples,
+                error_sender,
+            ),
+            SampleFormat::U16 => AudioPlaybackSession::build_output_stream::<u16>(
+                &device,
+                &config,
+                samples,
+                error_sender,
+            ),
+            _ => AudioPlaybackSession::build_output_stream::<f32>(
+                &device,
+                &config,
+                samples,
+                error_sender,
+            ),
+        }
+        .map_err(|e| AudioError::StreamOpenFailed(e.to_string()))?;
+
+        stream
+            .play()
+            .map_err(|e| AudioError::StreamOpenFailed(e.to_string()))?;
+        Ok((stream, device_name))
+    }
+
+    /// Builds the playback stream. The decoded 48khz audio is resampled to the device
+    /// rate, converted to the device format and copied on every output channel.
+    fn build_output_stream<T>(
+        device: &cpal::Device,
+        config: &SupportedStreamConfig,
+        samples: Arc<Mutex<VecDeque<f32>>>,
+        error_sender: Sender<cpal::StreamError>,
+    ) -> Result<Stream, cpal::BuildStreamError>
+    where
+        T: SizedSample + FromSample<f32>,
+    {
+        let channels = config.channels() as usize;
+        let sample_rate = config.sample_rate();
+        let mut resampler = LinearResampler::new(PLAYBACK_SAMPLE_RATE, sample_rate);
+        let mut decoded: Vec<f32> = Vec::new();
+        let mut resampled: Vec<f32> = Vec::new();
+        let mut pending: VecDeque<f32> = VecDeque::new();
+
+        let err_fn = move |err| {
+            let _ = error_sender.send(err);
         };
-        return s;
-    }
-
-    fn find_audio_config(
-        configs: Vec<SupportedStreamConfigRange>,
-    ) -> Option<SupportedStreamConfig> {
-        return if !configs.is_empty() {
-            let configs = configs.into_iter().filter(|c| {
-                return c.min_sample_rate() <= 48000
-                    && c.max_sample_rate() >= 48000
-                    && c.sample_format() == SampleFormat::F32
-                    && c.channels() == 1;
-            });
-            let x: Vec<SupportedStreamConfigRange> = configs.collect();
-            return x.first().map(|range| {
-                SupportedStreamConfig::new(1, 48000, range.buffer_size().clone(), SampleFormat::F32)
-            });
-        } else {
-            error!("No supported audio configs found");
-            None
+
+        let config = config.config();
+        device.build_output_stream(
+            config.into(),
+            move |data: &mut [T], _| {
+                let frames = data.len() / channels;
+                if pending.len() < frames {
+                    // Take just enough decoded samples to fill the device buffer
+                    let missing = (frames - pending.len()) as u64;
+                    let wanted = (missing * PLAYBACK_SAMPLE_RATE as u64)
+                        .div_ceil(sample_rate as u64) as usize;
+                    let mut samples = samples.lock().unwrap();
+                    let available = wanted.min(samples.len());
+                    decoded.clear();
+                    decoded.extend(samples.drain(..available));
+                    drop(samples);
+                    resampled.clear();
+                    resampler.process(&decoded, &mut resampled);
+                    pending.extend(&resampled);
+                }
+                for frame in data.chunks_mut(channels) {
+                    frame.fill(T::from_sample(pending.pop_front().unwrap_or(0.0)));
+                }
+            },
+            err_fn,
+            None,
+        )
+    }
+
+    fn find_output_device(host: &cpal::Host, device_name: &str) -> Option<cpal::Device> {
+        match host.output_devices() {
+            Ok(devices) => find_device(devices, device_name),
+            Err(e) => {
+                error!("Error listing audio output devices: {}", e);
+                None
+            }
+        }
+    }
+}
+
+impl Drop for AudioPlaybackSession {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Keeps an audio device stream open while it is in use, until the stop flag is set.
+/// The stream is reopened with a growing delay when it fails or can't be opened.
+fn supervise_stream<F, U>(
+    direction: AudioDirection,
+    mut open_stream: F,
+    mut in_use: U,
+    event_senders: AudioEventSenders,
+    stop: Arc<AtomicBool>,
+) where
+    F: FnMut(Sender<cpal::StreamError>) -> Result<(Stream, String), AudioError>,
+    U: FnMut() -> bool,
+{
+    let mut retry_delay = AUDIO_REOPEN_MIN_DELAY;
+    while !stop.load(Ordering::Relaxed) {
+        if !in_use() {
+            thread::sleep(AUDIO_STOP_CHECK_INTERVAL);
+            continue;
+        }
+
+        let (error_sender, error_receiver) = flume::unbounded();
+        let error = match open_stream(error_sender) {
+            Ok((stream, device_name)) => {
+                retry_delay = AUDIO_REOPEN_MIN_DELAY;
+                send_audio_event(
+                    &event_senders,
+                    AudioEvent::Started {
+                        direction,
+                        device_name,
+                    },
+                );
+                let error = loop {
+                    match error_receiver.recv_timeout(AUDIO_STOP_CHECK_INTERVAL) {
+                        Ok(error) => break Some(AudioError::StreamFailed(error.to_string())),
+                        Err(RecvTimeoutError::Timeout)
+                            if !stop.load(Ordering::Relaxed) && in_use() => {}
+                        Err(_) => break None,
+                    }
+                };
+                drop(stream);
+                match error {
+                    Some(error) => error,
+                    None => {
+                        send_audio_event(&event_senders, AudioEvent::Stopped { direction });
+                        continue;
+                    }
+                }
+            }
+            Err(error) => error,
         };
+
+        send_audio_event(
+            &event_senders,
+            AudioEvent::Failed {
+                direction,
+                error,
+                retry_in: retry_delay,
+            },
+        );
+        let retry_at = Instant::now() + retry_delay;
+        while Instant::now() < retry_at && !stop.load(Ordering::Relaxed) {
+            thread::sleep(AUDIO_STOP_CHECK_INTERVAL);
+        }
+        retry_delay = (retry_delay * 2).min(AUDIO_REOPEN_MAX_DELAY);
+    }
+    debug!("Audio {:?} stream supervisor ended", direction);
+}
+
+fn send_audio_event(event_senders: &AudioEventSenders, event: AudioEvent) {
+    match &event {
+        AudioEvent::Started {
+            direction,
+            device_name,
+        } => info!("Audio {:?} device started: {}", direction, device_name),
+        AudioEvent::Failed {
+            direction,
+            error,
+            retry_in,
+        } => error!(
+            "Audio {:?} device unavailable, retry in {} seconds: {}",
+            direction,
+            retry_in.as_secs(),
+            error
+        ),
+        AudioEvent::Stopped { direction } => info!("Audio {:?} device stopped", direction),
+        AudioEvent::FrameDropped { direction, error } => {
+            warn!("Audio {:?} frame dropped: {}", direction, error)
+        }
+    }
+    event_senders
+        .lock()
+        .unwrap()
+        .retain(|sender| sender.send(event.clone()).is_ok());
+}
+
+/// Picks a supported device config, preferring the given rate to avoid the resampling,
+/// then F32 over the integer formats, then the fewest channels.
+fn find_audio_config(
+    configs: Vec<SupportedStreamConfigRange>,
+    sample_rate: u32,
+) -> Option<SupportedStreamConfig> {
+    configs
+        .into_iter()
+        .filter(|c| DEVICE_SAMPLE_FORMATS.contains(&c.sample_format()))
+        .min_by_key(|c| {
+            (
+                c.min_sample_rate() > sample_rate || c.max_sample_rate() < sample_rate,
+                DEVICE_SAMPLE_FORMATS
+                    .iter()
+                    .position(|format| *format == c.sample_format()),
+                c.channels(),
+            )
+        })
+        .map(|range| {
+            SupportedStreamConfig::new(
+                range.channels(),
+                sample_rate.clamp(range.min_sample_rate(), range.max_sample_rate()),
+                *range.buffer_size(),
+                range.sample_format(),
+            )
+        })
+}
+
+fn device_label(device: &cpal::Device) -> String {
+    device
+        .description()
+        .map(|description| description.name().to_string())
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+fn find_device(
+    mut devices: impl Iterator<Item = cpal::Device>,
+    device_name: &str,
+) -> Option<cpal::Device> {
+    devices.find(|device| {
+        device
+            .description()
+            .map(|description| description.name() == device_name)
+            .unwrap_or(false)
+    })
+}
+
+fn check_encoder_profile(profile: &AudioEncoderProfile) -> Result<(), AudioEncoderProfileError> {
+    if let Some(bitrate) = profile.bitrate {
+        if !ENCODER_BITRATE_RANGE.contains(&bitrate) {
+            return Err(AudioEncoderProfileError::InvalidBitrate(bitrate));
+        }
+    }
+    if !ENCODER_FRAME_DURATIONS_MS.contains(&profile.frame_duration_ms) {
+        return Err(AudioEncoderProfileError::InvalidFrameDuration(
+            profile.frame_duration_ms,
+        ));
+    }
+    if profile.packet_loss_percent > 100 {
+        return Err(AudioEncoderProfileError::InvalidPacketLoss(
+            profile.packet_loss_percent,
+        ));
     }
+    Ok(())
 }
@@ -13,7 +13,77 @@ You should have received a copy of the GNU General Public License
 along with this program. If not, see <https://www.gnu.org/licenses/>
  */
 
-#[derive(Debug)]
-pub struct IOError {
-    pub message: String,
+use hamlib::errors::HamLibError;
+
+#[derive(thiserror::Error, Debug)]
+pub enum TransceiverError {
+    #[error("not supported by the transceiver: {0}")]
+    NotSupported(String),
+    #[error("invalid transceiver request: {0}")]
+    InvalidRequest(String),
+    #[error("rejected by the transceiver: {0}")]
+    Rejected(String),
+    #[error("transceiver disconnected")]
+    Disconnected,
+    #[error("transceiver error: {0}")]
+    Hamlib(HamLibError),
+}
+
+impl TransceiverError {
+    /// The link to the rig failed, unlike a rig missing a feature or refusing a request.
+    pub fn is_rig_failure(&self) -> bool {
+        match self {
+            Self::Disconnected => true,
+            Self::Hamlib(error) => error.is_link_failure(),
+            _ => false,
+        }
+    }
+}
+
+impl From<HamLibError> for TransceiverError {
+    fn from(error: HamLibError) -> Self {
+        if error.is_not_supported() {
+            Self::NotSupported(error.to_string())
+        } else if error.is_rejected() {
+            Self::Rejected(error.to_string())
+        } else {
+            Self::Hamlib(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TransceiverError;
+    use hamlib::errors::HamLibError;
+
+    #[test]
+    fn only_link_errors_are_rig_failures() {
+        for error in [
+            HamLibError::Timeout,
+            HamLibError::Io,
+            HamLibError::Protocol,
+            HamLibError::BusError,
+            HamLibError::BusBusy,
+        ] {
+            assert!(TransceiverError::from(error).is_rig_failure());
+        }
+        assert!(TransceiverError::Disconnected.is_rig_failure());
+        for error in [
+            HamLibError::Rejected,
+            HamLibError::InvalidParameter,
+            HamLibError::InvalidArgument,
+            HamLibError::InvalidVfo,
+            HamLibError::OutOfDomain,
+            HamLibError::Truncated,
+        ] {
+            let error = TransceiverError::from(error);
+            assert!(matches!(error, TransceiverError::Rejected(_)));
+            assert!(!error.is_rig_failure());
+        }
+        assert!(matches!(
+            TransceiverError::from(HamLibError::NotAvailable),
+            TransceiverError::NotSupported(_)
+        ));
+    }
 }
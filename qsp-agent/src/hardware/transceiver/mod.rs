@@ -12,5 +12,6 @@ General Public License for more details.
 You should have received a copy of the GNU General Public License
 along with this program. If not, see <https://www.gnu.org/licenses/>
  */
+pub mod rig_connection;
 pub mod transceiver_manager;
 pub mod transceiver_state;
@@ -0,0 +1,204 @@
+/*
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License,
+or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful, but
+WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program. If not, see <https://www.gnu.org/licenses/>
+ */
+
+use crate::hardware::transceiver::transceiver_state::TransceiverConnectionStatus;
+use std::time::{Duration, Instant};
+
+/// Consecutive failed polls after which the rig is considered lost and reopened.
+const MAX_FAILED_POLLS: u32 = 3;
+
+/// Connection state machine of the Hamlib rig, driven by the polling thread.
+///
+/// A failed poll degrades the connection. After `MAX_FAILED_POLLS` failed polls in
+/// a row the rig is disconnected, then reopened with the configured retry delays.
+/// Every transition returns the new status so it can be published to the clients.
+pub struct RigConnection {
+    status: TransceiverConnectionStatus,
+    failed_polls: u32,
+    failed_reconnects: usize,
+    retry_delays: Vec<Duration>,
+    next_reconnect: Instant,
+}
+
+impl RigConnection {
+    pub fn new(retry_delays: Vec<Duration>) -> Self {
+        Self {
+            status: TransceiverConnectionStatus::Connected,
+            failed_polls: 0,
+            failed_reconnects: 0,
+            retry_delays: if retry_delays.is_empty() {
+                vec![Duration::from_secs(1)]
+            } else {
+                retry_delays
+            },
+            next_reconnect: Instant::now(),
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        matches!(
+            self.status,
+            TransceiverConnectionStatus::Connected | TransceiverConnectionStatus::Degraded
+        )
+    }
+
+    pub fn poll_succeeded(&mut self) -> Option<TransceiverConnectionStatus> {
+        self.failed_polls = 0;
+        self.set_status(TransceiverConnectionStatus::Connected)
+    }
+
+    pub fn poll_failed(&mut self, now: Instant) -> Option<TransceiverConnectionStatus> {
+        self.failed_polls += 1;
+        if self.failed_polls < MAX_FAILED_POLLS {
+            return self.set_status(TransceiverConnectionStatus::Degraded);
+        }
+
+        self.disconnect(now)
+    }
+
+    /// The rig could not be opened at startup, it is reopened like a lost rig.
+    pub fn open_failed(&mut self, now: Instant) -> Option<TransceiverConnectionStatus> {
+        self.disconnect(now)
+    }
+
+    pub fn is_reconnect_due(&self, now: Instant) -> bool {
+        self.status == TransceiverConnectionStatus::Disconnected && now >= self.next_reconnect
+    }
+
+    pub fn reconnect_started(&mut self) -> Option<TransceiverConnectionStatus> {
+        self.set_status(TransceiverConnectionStatus::Reconnecting)
+    }
+
+    pub fn reconnect_succeeded(&mut self) -> Option<TransceiverConnectionStatus> {
+        self.failed_reconnects = 0;
+        self.set_status(TransceiverConnectionStatus::Connected)
+    }
+
+    pub fn reconnect_failed(&mut self, now: Instant) -> Option<TransceiverConnectionStatus> {
+        self.failed_reconnects += 1;
+        self.next_reconnect = now + self.retry_delay();
+        self.set_status(TransceiverConnectionStatus::Disconnected)
+    }
+
+    /// Delay before the next reconnection attempt. The last delay is reused once
+    /// all the delays are used.
+    pub fn retry_delay(&self) -> Duration {
+        self.retry_delays
+            .get(self.failed_reconnects)
+            .copied()
+            .unwrap_or_else(|| *self.retry_delays.last().unwrap())
+    }
+
+    fn disconnect(&mut self, now: Instant) -> Option<TransceiverConnectionStatus> {
+        self.failed_polls = 0;
+        self.failed_reconnects = 0;
+        self.next_reconnect = now + self.retry_delay();
+        self.set_status(TransceiverConnectionStatus::Disconnected)
+    }
+
+    fn set_status(
+        &mut self,
+        status: TransceiverConnectionStatus,
+    ) -> Option<TransceiverConnectionStatus> {
+        if self.status == status {
+            return None;
+        }
+        self.status = status;
+        Some(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RigConnection, MAX_FAILED_POLLS};
+    use crate::hardware::transceiver::transceiver_state::TransceiverConnectionStatus;
+    use std::time::{Duration, Instant};
+
+    fn delays() -> Vec<Duration> {
+        vec![Duration::from_secs(1), Duration::from_secs(5)]
+    }
+
+    #[test]
+    fn failed_polls_degrade_then_disconnect() {
+        let mut connection = RigConnection::new(delays());
+        let now = Instant::now();
+
+        assert_eq!(
+            connection.poll_failed(now),
+            Some(TransceiverConnectionStatus::Degraded)
+        );
+        for _ in 2..MAX_FAILED_POLLS {
+            assert_eq!(connection.poll_failed(now), None);
+        }
+        assert_eq!(
+            connection.poll_failed(now),
+            Some(TransceiverConnectionStatus::Disconnected)
+        );
+        assert!(!connection.is_connected());
+    }
+
+    #[test]
+    fn successful_poll_restores_degraded_connection() {
+        let mut connection = RigConnection::new(delays());
+        connection.poll_failed(Instant::now());
+
+        assert_eq!(
+            connection.poll_succeeded(),
+            Some(TransceiverConnectionStatus::Connected)
+        );
+        assert_eq!(connection.poll_succeeded(), None);
+    }
+
+    #[test]
+    fn failed_open_waits_for_reconnect() {
+        let mut connection = RigConnection::new(delays());
+        let now = Instant::now();
+
+        assert_eq!(
+            connection.open_failed(now),
+            Some(TransceiverConnectionStatus::Disconnected)
+        );
+        assert!(!connection.is_connected());
+        assert!(!connection.is_reconnect_due(now));
+        assert!(connection.is_reconnect_due(now + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn reconnect_attempts_back_off() {
+        let mut connection = RigConnection::new(delays());
+        let now = Instant::now();
+        for _ in 0..MAX_FAILED_POLLS {
+            connection.poll_failed(now);
+        }
+
+        assert!(!connection.is_reconnect_due(now));
+        assert!(connection.is_reconnect_due(now + Duration::from_secs(1)));
+
+        connection.reconnect_started();
+        connection.reconnect_failed(now);
+        assert_eq!(connection.retry_delay(), Duration::from_secs(5));
+        connection.reconnect_started();
+        connection.reconnect_failed(now);
+        assert_eq!(connection.retry_delay(), Duration::from_secs(5));
+        assert!(!connection.is_reconnect_due(now + Duration::from_secs(4)));
+
+        connection.reconnect_started();
+        assert_eq!(
+            connection.reconnect_succeeded(),
+            Some(TransceiverConnectionStatus::Connected)
+        );
+        assert_eq!(connection.retry_delay(), Duration::from_secs(1));
+    }
+}
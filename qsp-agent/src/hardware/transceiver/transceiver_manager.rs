@@ -14,30 +14,63 @@ along with this program. If not, see <https://www.gnu.org/licenses/>
  */
 
 use crate::configuration::{Configuration, HamlibDebugLevel as ConfigHamlibDebugLevel};
-use crate::hardware::error::IOError;
+use crate::hardware::error::TransceiverError;
+use crate::hardware::transceiver::rig_connection::RigConnection;
 use crate::hardware::transceiver::transceiver_state::{
-    TransceiverBand, TransceiverMode, TransceiverParameter, TransceiverState,
-    TransceiverStateMessage, TransceiverSubsystem,
+    TransceiverBand, TransceiverConnectionStatus, TransceiverFunction, TransceiverLevel,
+    TransceiverMeterReading, TransceiverMode, TransceiverParameter, TransceiverPassband,
+    TransceiverPtt, TransceiverSplit, TransceiverState, TransceiverStateMessage,
+    TransceiverSubsystem, TransceiverVfoState,
 };
-use hamlib::hamlib::{Hamlib, RigCaps, RigDebugLevel};
+use hamlib::errors::HamLibError;
+use hamlib::hamlib::{Hamlib, RigCaps, RigDebugLevel, RigFrequencyRange};
 use hamlib::rig::{Rig, RigVfoOperation};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tracing::{debug, error, info, trace, warn};
 
+/// Retries of a rig command that timed out, the usual symptom of a CAT command lost on
+/// a noisy line.
+const COMMAND_TIMEOUT_RETRIES: u32 = 2;
+
+/// Hamlib addresses the current VFO when no VFO is given.
+const CURRENT_VFO: u32 = 0;
+
+/// Interval between reads of the rig levels and functions. They only change on a command
+/// or from the rig front panel, and reading them all at each state poll saturates slow CAT
+/// links.
+const SETTINGS_POLLING_INTERVAL: Duration = Duration::from_secs(10);
+
 pub struct TransceiverManager {
     hamlib: Hamlib,
     rig: Mutex<Rig>,
     state: Mutex<TransceiverState>,
     caps: Mutex<RigCaps>,
+    connection: Mutex<RigConnection>,
     state_polling_interval: Duration,
+    meter_polling_interval: Duration,
+    next_settings_poll: Mutex<Instant>,
     state_update_senders: Mutex<Vec<UnboundedSender<TransceiverStateMessage>>>,
+    transmission: Mutex<Option<Transmission>>,
+    // PTT commands sent so far, a PTT read older than the last command is ignored
+    ptt_commands: AtomicU64,
+    // None when the transmit timeout is disabled
+    max_transmit_time: Option<Duration>,
+}
+
+/// Transmission keyed by a remote session, watched to never leave the rig keyed.
+struct Transmission {
+    session_uuid: String,
+    vfo_id: u32,
+    started_at: Instant,
 }
 
 impl TransceiverManager {
-    pub fn new(configuration: Configuration) -> Result<Arc<TransceiverManager>, IOError> {
+    pub fn new(configuration: Configuration) -> Result<Arc<TransceiverManager>, TransceiverError> {
         debug!("Hamlib init");
         let mut hamlib = Hamlib::new();
         Hamlib::rig_set_debug_callback(Some(Box::new(|level: RigDebugLevel, message: &str| {
@@ -61,107 +94,603 @@ impl TransceiverManager {
             Hamlib::rig_set_debug(debug_level);
         }
 
-        let rig = hamlib
-            .rig_connect(
-                configuration.transceiver.rig_model,
-                configuration.transceiver.port.clone(),
-            )
-            .map_err(|e| IOError {
-                message: e.message.to_string(),
-            })?;
-        let caps = rig.caps().ok_or(IOError {
-            message: "hamlib rig caps unavailable".to_string(),
-        })?;
+        // A configuration error is fatal, a rig that can't be opened is reopened later
+        let mut rig = hamlib.rig_init(
+            configuration.transceiver.rig_model,
+            configuration.transceiver.port.clone(),
+        )?;
+        let mut connection = RigConnection::new(
+            configuration
+                .transceiver
+                .reconnect_retry_delay_seconds
+                .iter()
+                .map(|seconds| Duration::from_secs(*seconds))
+                .collect(),
+        );
+        let mut connection_status = TransceiverConnectionStatus::Connected;
+        if let Err(error) = rig.reopen() {
+            if let Some(status) = connection.open_failed(Instant::now()) {
+                connection_status = status;
+            }
+            warn!(
+                "Failed to open the transceiver: {}. Retrying in {} seconds",
+                error,
+                connection.retry_delay().as_secs()
+            );
+        }
+        // Static capabilities of the model until the rig is opened
+        let caps = rig
+            .caps()
+            .ok_or(TransceiverError::Hamlib(HamLibError::Internal))?;
 
         let manager = Arc::new(TransceiverManager {
             hamlib,
             rig: Mutex::new(rig),
             state: Mutex::new(TransceiverState {
-                main_vfo_freq: 0,
-                main_vfo_mode: None,
+                vfos: BTreeMap::new(),
+                ptt: TransceiverPtt::Off,
+                split: None,
+                rit_hz: None,
+                xit_hz: None,
+                levels: vec![],
+                functions: vec![],
+                antennas: None,
+                connection: connection_status,
             }),
             caps: Mutex::new(caps),
+            connection: Mutex::new(connection),
             state_polling_interval: Duration::from_millis(
                 configuration.transceiver.state_polling_interval_ms,
             ),
+            meter_polling_interval: Duration::from_millis(
+                configuration.transceiver.meter_polling_interval_ms,
+            ),
+            next_settings_poll: Mutex::new(Instant::now()),
             state_update_senders: Mutex::new(vec![]),
+            transmission: Mutex::new(None),
+            ptt_commands: AtomicU64::new(0),
+            max_transmit_time: Some(configuration.transceiver.max_transmit_time_seconds)
+                .filter(|seconds| *seconds > 0)
+                .map(Duration::from_secs),
         });
 
         let polling_manager = Arc::clone(&manager);
         thread::spawn(move || polling_manager.state_polling_thread_loop());
+        let meter_polling_manager = Arc::clone(&manager);
+        thread::spawn(move || meter_polling_manager.meter_polling_thread_loop());
 
         Ok(manager)
     }
 
-    pub fn full_state_update(&self) -> Result<bool, IOError> {
+    pub fn full_state_update(&self) -> Result<bool, TransceiverError> {
         let mut updated = false;
+        let (
+            poll_all_vfos,
+            has_split,
+            has_rit,
+            has_xit,
+            polled_levels,
+            polled_functions,
+            has_antennas,
+        ) = {
+            let caps = self.caps.lock().unwrap();
+            (
+                // Reading another VFO would switch the current VFO back and forth on the rig
+                caps.targetable_freq && caps.targetable_mode,
+                caps.can_get_split,
+                caps.max_rit_hz > 0,
+                caps.max_xit_hz > 0,
+                caps.set_levels
+                    .iter()
+                    .map(|range| range.level)
+                    .filter(|level| caps.get_levels.contains(level))
+                    .collect::<Vec<_>>(),
+                caps.get_functions.clone(),
+                caps.can_get_antennas,
+            )
+        };
+        let poll_settings = Instant::now() >= *self.next_settings_poll.lock().unwrap();
+        // The transmission isn't locked during the rig reads, so a slow poll never delays
+        // unkeying the rig
         let rig = self.rig.lock().unwrap();
-        let freq = rig.get_freq(0).map_err(|e| IOError {
-            message: e.message.to_string(),
-        })?;
-        let mode = rig.get_mode(0).map_err(|e| IOError {
-            message: e.message.to_string(),
-        })?;
-        let mode = TransceiverMode::from_hamlib_name(&mode).ok_or(IOError {
-            message: format!("unsupported hamlib mode: {mode}"),
-        })?;
-        let freq = freq as u64;
+        let mut vfos = BTreeMap::new();
+        vfos.insert(CURRENT_VFO, read_vfo_state(&rig, CURRENT_VFO)?);
+        if poll_all_vfos {
+            for vfo in rig.vfos() {
+                match read_vfo_state(&rig, vfo) {
+                    Ok(vfo_state) => {
+                        vfos.insert(vfo, vfo_state);
+                    }
+                    // Some backends list VFOs they can't address, and reject
+                    // them as not targetable, invalid VFO or invalid parameter
+                    Err(
+                        error @ (TransceiverError::NotSupported(_) | TransceiverError::Rejected(_)),
+                    ) => {
+                        trace!("Failed to read VFO {} state: {}", vfo, error)
+                    }
+                    Err(error) => return Err(error),
+                }
+            }
+        }
+        // Not every rig can report PTT, keep the last commanded state in that case
+        let ptt_commands = self.ptt_commands.load(Ordering::Relaxed);
+        let ptt = match rig.get_ptt(CURRENT_VFO) {
+            Ok(ptt) => Some(ptt),
+            Err(error) => {
+                trace!("Failed to read transceiver PTT: {}", error);
+                None
+            }
+        };
+        let split = if has_split {
+            optional_state("split", read_split_state(&rig))?
+        } else {
+            None
+        };
+        let rit_hz = if has_rit {
+            optional_state(
+                "RIT",
+                retry_on_timeout(|| rig.get_rit(CURRENT_VFO)).map_err(TransceiverError::from),
+            )?
+        } else {
+            None
+        };
+        let xit_hz = if has_xit {
+            optional_state(
+                "XIT",
+                retry_on_timeout(|| rig.get_xit(CURRENT_VFO)).map_err(TransceiverError::from),
+            )?
+        } else {
+            None
+        };
+        let levels = if poll_settings {
+            let mut levels = Vec::with_capacity(polled_levels.len());
+            for level in polled_levels {
+                let value = optional_state(
+                    "level",
+                    retry_on_timeout(|| rig.get_level(CURRENT_VFO, level))
+                        .map_err(TransceiverError::from),
+                )?;
+                if let Some(value) = value {
+                    levels.push((level, value));
+                }
+            }
+            Some(levels)
+        } else {
+            None
+        };
+        let functions = if poll_settings {
+            let mut functions = Vec::with_capacity(polled_functions.len());
+            for function in polled_functions {
+                let enabled = optional_state(
+                    "function",
+                    retry_on_timeout(|| rig.get_func(CURRENT_VFO, function))
+                        .map_err(TransceiverError::from),
+                )?;
+                if let Some(enabled) = enabled {
+                    functions.push((function, enabled));
+                }
+            }
+            Some(functions)
+        } else {
+            None
+        };
+        let antennas = if has_antennas {
+            optional_state(
+                "antennas",
+                retry_on_timeout(|| rig.get_ant(CURRENT_VFO)).map_err(TransceiverError::from),
+            )?
+        } else {
+            None
+        };
         drop(rig);
 
+        let mut transmission = self.transmission.lock().unwrap();
+        // A PTT command sent during the poll makes the PTT read stale
+        let ptt = ptt.filter(|_| self.ptt_commands.load(Ordering::Relaxed) == ptt_commands);
         let mut state = self.state.lock().unwrap();
-        if state.main_vfo_freq != freq {
-            state.main_vfo_freq = freq;
+        if state.vfos != vfos {
+            state.vfos = vfos;
+            updated = true;
+        }
+        if let Some(ptt) = ptt.filter(|ptt| state.ptt != *ptt) {
+            state.ptt = ptt;
+            updated = true;
+        }
+        if state.split != split {
+            state.split = split;
             updated = true;
         }
-        if state.main_vfo_mode.as_ref() != Some(&mode) {
-            state.main_vfo_mode = Some(mode);
+        if state.rit_hz != rit_hz || state.xit_hz != xit_hz {
+            state.rit_hz = rit_hz;
+            state.xit_hz = xit_hz;
             updated = true;
         }
+        if let Some(levels) = levels.filter(|levels| state.levels != *levels) {
+            state.levels = levels;
+            updated = true;
+        }
+        if let Some(functions) = functions.filter(|functions| state.functions != *functions) {
+            state.functions = functions;
+            updated = true;
+        }
+        if state.antennas != antennas {
+            state.antennas = antennas;
+            updated = true;
+        }
+        drop(state);
+
+        if ptt.is_some_and(|ptt| !ptt.is_keyed()) {
+            // The rig is back to receive by itself (local operator, rig TOT...)
+            transmission.take();
+        }
+        if poll_settings {
+            *self.next_settings_poll.lock().unwrap() = Instant::now() + SETTINGS_POLLING_INTERVAL;
+        }
 
         Ok(updated)
     }
 
-    pub fn set_frequency(&self, vfo_id: u32, frequency: u64) {
-        self.rig.lock().unwrap().set_freq(vfo_id, frequency as f64);
+    pub fn set_frequency(&self, vfo_id: u32, frequency: u64) -> Result<(), TransceiverError> {
+        self.check_connected()?;
+        let transmitting = self.transmission.lock().unwrap().is_some();
+        {
+            let caps = self.caps.lock().unwrap();
+            if !is_frequency_in_ranges(&caps.rx_frequency_ranges, frequency) {
+                return Err(TransceiverError::InvalidRequest(format!(
+                    "frequency {frequency} Hz out of the receive ranges"
+                )));
+            }
+            // Moving the VFO while keyed must not leave the transmit ranges
+            if transmitting && !is_frequency_in_ranges(&caps.tx_frequency_ranges, frequency) {
+                return Err(TransceiverError::InvalidRequest(format!(
+                    "frequency {frequency} Hz out of the transmit ranges"
+                )));
+            }
+        }
+
+        let rig = self.rig.lock().unwrap();
+        retry_on_timeout(|| rig.set_freq(vfo_id, frequency as f64))?;
+        Ok(())
     }
 
-    pub fn set_mode(&self, vfo_id: u32, mode: TransceiverMode) -> Result<(), IOError> {
-        self.rig
-            .lock()
-            .unwrap()
-            .set_mode(vfo_id, mode.as_hamlib_name())
-            .map_err(|e| IOError {
-                message: e.message.to_string(),
-            })
+    pub fn set_mode(
+        &self,
+        vfo_id: u32,
+        mode: TransceiverMode,
+        passband: TransceiverPassband,
+    ) -> Result<(), TransceiverError> {
+        self.check_connected()?;
+        if passband == TransceiverPassband::Hz(0) {
+            return Err(TransceiverError::InvalidRequest(
+                "passband width of 0 Hz".to_string(),
+            ));
+        }
+
+        let rig = self.rig.lock().unwrap();
+        retry_on_timeout(|| rig.set_mode(vfo_id, mode.as_hamlib_name(), passband))?;
+        Ok(())
     }
 
-    pub fn set_band(&self, band: TransceiverBand) -> Result<(), IOError> {
-        let band = band.as_hamlib_name().ok_or(IOError {
-            message: format!("unsupported hamlib band: {band:?}"),
-        })?;
+    pub fn set_split(
+        &self,
+        vfo_id: u32,
+        enabled: bool,
+        tx_vfo_id: u32,
+    ) -> Result<(), TransceiverError> {
+        self.check_connected()?;
+        let rig = self.rig.lock().unwrap();
+        retry_on_timeout(|| rig.set_split_vfo(vfo_id, enabled, tx_vfo_id))?;
+        Ok(())
+    }
+
+    pub fn set_split_frequency(
+        &self,
+        vfo_id: u32,
+        tx_frequency: u64,
+    ) -> Result<(), TransceiverError> {
+        self.check_connected()?;
+        self.check_split_transmit(Some(tx_frequency), None)?;
+
+        let rig = self.rig.lock().unwrap();
+        retry_on_timeout(|| rig.set_split_freq(vfo_id, tx_frequency as f64))?;
+        Ok(())
+    }
+
+    /// Checks a split TX frequency and mode against the transmit ranges, so a split
+    /// command can be refused before anything is sent to the rig.
+    pub fn check_split_transmit(
+        &self,
+        tx_frequency: Option<u64>,
+        tx_mode: Option<TransceiverMode>,
+    ) -> Result<(), TransceiverError> {
+        let caps = self.caps.lock().unwrap();
+        if let Some(tx_frequency) = tx_frequency {
+            if !is_frequency_in_ranges(&caps.tx_frequency_ranges, tx_frequency) {
+                return Err(TransceiverError::InvalidRequest(format!(
+                    "split frequency {tx_frequency} Hz out of the transmit ranges"
+                )));
+            }
+        }
+        if let Some(tx_mode) = tx_mode {
+            let mode_allowed = caps.tx_frequency_ranges.is_empty()
+                || caps
+                    .tx_frequency_ranges
+                    .iter()
+                    .filter(|range| {
+                        tx_frequency.is_none_or(|frequency| {
+                            range.lower_frequency_hz <= frequency
+                                && frequency <= range.upper_frequency_hz
+                        })
+                    })
+                    .any(|range| range.modes.is_empty() || range.modes.contains(&tx_mode));
+            if !mode_allowed {
+                return Err(TransceiverError::InvalidRequest(format!(
+                    "split mode {tx_mode:?} out of the transmit ranges"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_split_mode(
+        &self,
+        vfo_id: u32,
+        tx_mode: TransceiverMode,
+    ) -> Result<(), TransceiverError> {
+        self.check_connected()?;
+        self.check_split_transmit(None, Some(tx_mode))?;
+        let rig = self.rig.lock().unwrap();
+        retry_on_timeout(|| rig.set_split_mode(vfo_id, tx_mode.as_hamlib_name()))?;
+        Ok(())
+    }
+
+    pub fn set_rit(&self, vfo_id: u32, offset_hz: i32) -> Result<(), TransceiverError> {
+        self.check_connected()?;
+        let max_rit_hz = self.caps.lock().unwrap().max_rit_hz;
+        check_offset("RIT", offset_hz, max_rit_hz)?;
+
+        let rig = self.rig.lock().unwrap();
+        retry_on_timeout(|| rig.set_rit(vfo_id, offset_hz))?;
+        Ok(())
+    }
 
-        self.rig
+    pub fn set_xit(&self, vfo_id: u32, offset_hz: i32) -> Result<(), TransceiverError> {
+        self.check_connected()?;
+        let max_xit_hz = self.caps.lock().unwrap().max_xit_hz;
+        check_offset("XIT", offset_hz, max_xit_hz)?;
+
+        let rig = self.rig.lock().unwrap();
+        retry_on_timeout(|| rig.set_xit(vfo_id, offset_hz))?;
+        Ok(())
+    }
+
+    pub fn set_level(&self, level: TransceiverLevel, value: f32) -> Result<(), TransceiverError> {
+        self.check_connected()?;
+        let range = self
+            .caps
             .lock()
             .unwrap()
-            .set_band(band)
-            .map_err(|e| IOError {
-                message: e.message.to_string(),
-            })
+            .set_levels
+            .iter()
+            .find(|range| range.level == level)
+            .copied()
+            .ok_or_else(|| TransceiverError::NotSupported(format!("level {level:?}")))?;
+        if !range.contains(value) {
+            return Err(TransceiverError::InvalidRequest(format!(
+                "level {level:?} value {value} out of the rig range"
+            )));
+        }
+
+        let rig = self.rig.lock().unwrap();
+        retry_on_timeout(|| rig.set_level(CURRENT_VFO, level, value))?;
+        drop(rig);
+
+        // Levels are only polled now and then, report the new value right away
+        let updated = update_setting(&mut self.state.lock().unwrap().levels, level, value);
+        if updated {
+            self.send_current_state();
+        }
+        Ok(())
     }
 
-    pub fn vfo_operation(&self, vfo_id: u32, operation: RigVfoOperation) -> Result<(), IOError> {
+    pub fn set_function(
+        &self,
+        function: TransceiverFunction,
+        enabled: bool,
+    ) -> Result<(), TransceiverError> {
+        self.check_connected()?;
+        if !self.caps.lock().unwrap().set_functions.contains(&function) {
+            return Err(TransceiverError::NotSupported(format!(
+                "function {function:?}"
+            )));
+        }
+
+        let rig = self.rig.lock().unwrap();
+        retry_on_timeout(|| rig.set_func(CURRENT_VFO, function, enabled))?;
+        drop(rig);
+
+        // Functions are only polled now and then, report the new state right away
+        let updated = update_setting(&mut self.state.lock().unwrap().functions, function, enabled);
+        if updated {
+            self.send_current_state();
+        }
+        Ok(())
+    }
+
+    /// Selects a single antenna, given as a Hamlib antenna flag, with the antenna option.
+    pub fn set_antenna(
+        &self,
+        vfo_id: u32,
+        antenna: u32,
+        option: i32,
+    ) -> Result<(), TransceiverError> {
+        self.check_connected()?;
+        if antenna.count_ones() != 1 {
+            return Err(TransceiverError::InvalidRequest(format!(
+                "antenna flags 0x{antenna:08x} don't select a single antenna"
+            )));
+        }
+        {
+            let caps = self.caps.lock().unwrap();
+            // Rigs declaring no antenna in their ranges are left to Hamlib
+            let rig_antennas = caps
+                .rx_frequency_ranges
+                .iter()
+                .chain(caps.tx_frequency_ranges.iter())
+                .fold(0, |antennas, range| antennas | range.antenna);
+            if rig_antennas != 0 && rig_antennas & antenna == 0 {
+                return Err(TransceiverError::InvalidRequest(format!(
+                    "antenna flags 0x{antenna:08x} not available on the rig"
+                )));
+            }
+        }
+
+        let rig = self.rig.lock().unwrap();
+        retry_on_timeout(|| rig.set_ant(vfo_id, antenna, option))?;
+        Ok(())
+    }
+
+    pub fn set_band(&self, band: TransceiverBand) -> Result<(), TransceiverError> {
+        self.check_connected()?;
+        let band = band
+            .as_hamlib_name()
+            .ok_or_else(|| TransceiverError::NotSupported(format!("band {band:?}")))?;
+
+        let rig = self.rig.lock().unwrap();
+        retry_on_timeout(|| rig.set_band(band))?;
+        Ok(())
+    }
+
+    pub fn vfo_operation(
+        &self,
+        vfo_id: u32,
+        operation: RigVfoOperation,
+    ) -> Result<(), TransceiverError> {
+        self.check_connected()?;
         if !self.is_vfo_operation_supported(operation) {
-            info!("Unsupported VFO operation {:?}", operation);
-            return Ok(());
+            return Err(TransceiverError::NotSupported(format!(
+                "VFO operation {operation:?}"
+            )));
         }
 
-        self.rig
-            .lock()
-            .unwrap()
-            .vfo_op(vfo_id, operation)
-            .map_err(|e| IOError {
-                message: e.message.to_string(),
-            })
+        let rig = self.rig.lock().unwrap();
+        retry_on_timeout(|| rig.vfo_op(vfo_id, operation))?;
+        Ok(())
+    }
+
+    pub fn set_ptt(
+        &self,
+        session_uuid: &str,
+        vfo_id: u32,
+        ptt: TransceiverPtt,
+    ) -> Result<(), TransceiverError> {
+        // Unkeying is always tried, the rig may still be keyed while its link is down
+        if ptt.is_keyed() {
+            self.check_connected()?;
+        }
+        let mut transmission = self.transmission.lock().unwrap();
+        {
+            let rig = self.rig.lock().unwrap();
+            retry_on_timeout(|| rig.set_ptt(vfo_id, ptt))?;
+        }
+        self.ptt_commands.fetch_add(1, Ordering::Relaxed);
+        info!(
+            "Transceiver PTT set to {:?} by session {}",
+            ptt, session_uuid
+        );
+
+        if ptt.is_keyed() {
+            // Keep the start time when an already keyed rig only switches PTT source
+            let started_at = transmission
+                .as_ref()
+                .map(|transmission| transmission.started_at)
+                .unwrap_or_else(Instant::now);
+            *transmission = Some(Transmission {
+                session_uuid: session_uuid.to_string(),
+                vfo_id,
+                started_at,
+            });
+        } else {
+            *transmission = None;
+        }
+        drop(transmission);
+        self.update_ptt_state(ptt);
+
+        Ok(())
+    }
+
+    /// Unkeys the transmitter if it was keyed by the given session.
+    pub fn release_ptt(&self, session_uuid: &str) {
+        let mut transmission = self.transmission.lock().unwrap();
+        let Some(vfo_id) = transmission
+            .as_ref()
+            .filter(|transmission| transmission.session_uuid == session_uuid)
+            .map(|transmission| transmission.vfo_id)
+        else {
+            return;
+        };
+
+        warn!(
+            "Session {} lost while transmitting. Release PTT",
+            session_uuid
+        );
+        if self.unkey(vfo_id) {
+            *transmission = None;
+        }
+    }
+
+    fn check_transmit_timeout(&self) {
+        let Some(max_transmit_time) = self.max_transmit_time else {
+            return;
+        };
+        let mut transmission = self.transmission.lock().unwrap();
+        let Some(vfo_id) = transmission
+            .as_ref()
+            .filter(|transmission| transmission.started_at.elapsed() >= max_transmit_time)
+            .map(|transmission| transmission.vfo_id)
+        else {
+            return;
+        };
+
+        warn!(
+            "Maximum transmit time of {} seconds reached. Release PTT",
+            max_transmit_time.as_secs()
+        );
+        if self.unkey(vfo_id) {
+            *transmission = None;
+        }
+    }
+
+    // On failure the transmission is kept so the watchdog tries again on next poll
+    fn unkey(&self, vfo_id: u32) -> bool {
+        let result = {
+            let rig = self.rig.lock().unwrap();
+            retry_on_timeout(|| rig.set_ptt(vfo_id, TransceiverPtt::Off))
+        };
+        match result {
+            Ok(()) => {
+                self.ptt_commands.fetch_add(1, Ordering::Relaxed);
+                self.update_ptt_state(TransceiverPtt::Off);
+                true
+            }
+            Err(error) => {
+                error!("Failed to release transceiver PTT: {}", error);
+                false
+            }
+        }
+    }
+
+    // PTT is a rig wide state, published like the polled one under the current VFO
+    fn update_ptt_state(&self, ptt: TransceiverPtt) {
+        let mut state = self.state.lock().unwrap();
+        if state.ptt != ptt {
+            state.ptt = ptt;
+            drop(state);
+            self.send_state_update(TransceiverStateMessage {
+                subsystem: TransceiverSubsystem::Vfo { id: CURRENT_VFO },
+                parameter: TransceiverParameter::Ptt { ptt },
+            });
+        }
     }
 
     pub fn add_state_update_receiver(&self) -> UnboundedReceiver<TransceiverStateMessage> {
@@ -172,18 +701,67 @@ impl TransceiverManager {
 
     pub fn send_current_state(&self) {
         let state = self.state.lock().unwrap().clone();
+        for (id, vfo) in state.vfos {
+            self.send_state_update(TransceiverStateMessage {
+                subsystem: TransceiverSubsystem::Vfo { id },
+                parameter: TransceiverParameter::Frequency { freq: vfo.freq },
+            });
+            if let Some(mode) = vfo.mode {
+                self.send_state_update(TransceiverStateMessage {
+                    subsystem: TransceiverSubsystem::Vfo { id },
+                    parameter: TransceiverParameter::Mode {
+                        mode,
+                        passband_hz: vfo.passband_hz,
+                    },
+                });
+            }
+        }
         self.send_state_update(TransceiverStateMessage {
-            subsystem: TransceiverSubsystem::Vfo { id: 0 },
-            parameter: TransceiverParameter::Frequency {
-                freq: state.main_vfo_freq,
-            },
+            subsystem: TransceiverSubsystem::Vfo { id: CURRENT_VFO },
+            parameter: TransceiverParameter::Ptt { ptt: state.ptt },
         });
-        if let Some(mode) = state.main_vfo_mode {
+        if let Some(split) = state.split {
+            self.send_state_update(TransceiverStateMessage {
+                subsystem: TransceiverSubsystem::Vfo { id: CURRENT_VFO },
+                parameter: TransceiverParameter::Split { split },
+            });
+        }
+        if let Some(offset_hz) = state.rit_hz {
+            self.send_state_update(TransceiverStateMessage {
+                subsystem: TransceiverSubsystem::Vfo { id: CURRENT_VFO },
+                parameter: TransceiverParameter::Rit { offset_hz },
+            });
+        }
+        if let Some(offset_hz) = state.xit_hz {
+            self.send_state_update(TransceiverStateMessage {
+                subsystem: TransceiverSubsystem::Vfo { id: CURRENT_VFO },
+                parameter: TransceiverParameter::Xit { offset_hz },
+            });
+        }
+        for (level, value) in state.levels {
             self.send_state_update(TransceiverStateMessage {
-                subsystem: TransceiverSubsystem::Vfo { id: 0 },
-                parameter: TransceiverParameter::Mode { mode },
+                subsystem: TransceiverSubsystem::Rig,
+                parameter: TransceiverParameter::Level { level, value },
             });
         }
+        for (function, enabled) in state.functions {
+            self.send_state_update(TransceiverStateMessage {
+                subsystem: TransceiverSubsystem::Rig,
+                parameter: TransceiverParameter::Function { function, enabled },
+            });
+        }
+        if let Some(antennas) = state.antennas {
+            self.send_state_update(TransceiverStateMessage {
+                subsystem: TransceiverSubsystem::Vfo { id: CURRENT_VFO },
+                parameter: TransceiverParameter::Antennas { antennas },
+            });
+        }
+        self.send_state_update(TransceiverStateMessage {
+            subsystem: TransceiverSubsystem::Rig,
+            parameter: TransceiverParameter::Connection {
+                status: state.connection,
+            },
+        });
     }
 
     fn send_state_update(&self, update: TransceiverStateMessage) {
@@ -198,15 +776,11 @@ impl TransceiverManager {
 
         loop {
             next_poll += self.state_polling_interval;
-            match self.full_state_update() {
-                Ok(updated) => {
-                    if updated {
-                        self.send_current_state()
-                    }
-                }
-                Err(error) => {
-                    error!("Failed to update transceiver state: {}", error.message);
-                }
+            if self.connection.lock().unwrap().is_connected() {
+                self.poll_state();
+                self.check_transmit_timeout();
+            } else {
+                self.try_reconnect();
             }
 
             let now = Instant::now();
@@ -218,6 +792,165 @@ impl TransceiverManager {
         }
     }
 
+    fn meter_polling_thread_loop(&self) {
+        let mut next_poll = Instant::now();
+
+        loop {
+            next_poll += self.meter_polling_interval;
+            // Meters are only worth the CAT traffic while someone is listening
+            let has_receivers = !self.state_update_senders.lock().unwrap().is_empty();
+            if has_receivers && self.connection.lock().unwrap().is_connected() {
+                self.poll_meters();
+            }
+
+            let now = Instant::now();
+            if next_poll > now {
+                thread::sleep(next_poll - now);
+            } else {
+                next_poll = now;
+            }
+        }
+    }
+
+    fn poll_meters(&self) {
+        let transmitting = self.state.lock().unwrap().ptt.is_keyed();
+        let levels: Vec<_> = self
+            .caps
+            .lock()
+            .unwrap()
+            .get_levels
+            .iter()
+            .copied()
+            .filter(|level| level.is_meter() && level.is_transmit_meter() == transmitting)
+            .collect();
+        if levels.is_empty() {
+            return;
+        }
+
+        let mut readings = Vec::with_capacity(levels.len());
+        {
+            let rig = self.rig.lock().unwrap();
+            for level in levels {
+                // A missed reading is replaced by the next one, the state poll tracks rig failures
+                match rig.get_level(CURRENT_VFO, level) {
+                    Ok(value) => readings.push(TransceiverMeterReading { level, value }),
+                    Err(error) => trace!("Failed to read transceiver {:?}: {}", level, error),
+                }
+            }
+        }
+
+        if !readings.is_empty() {
+            self.send_state_update(TransceiverStateMessage {
+                subsystem: TransceiverSubsystem::Rig,
+                parameter: TransceiverParameter::Meters { readings },
+            });
+        }
+    }
+
+    fn poll_state(&self) {
+        match self.full_state_update() {
+            Ok(updated) => {
+                let status = self.connection.lock().unwrap().poll_succeeded();
+                self.publish_connection_status(status);
+                if updated {
+                    self.send_current_state()
+                }
+            }
+            Err(error) if !error.is_rig_failure() => {
+                // The rig answered, it just can't provide some of the state
+                warn!("Failed to update transceiver state: {}", error);
+                let status = self.connection.lock().unwrap().poll_succeeded();
+                self.publish_connection_status(status);
+            }
+            Err(error) => {
+                error!("Failed to update transceiver state: {}", error);
+                let mut connection = self.connection.lock().unwrap();
+                let status = connection.poll_failed(Instant::now());
+                if status == Some(TransceiverConnectionStatus::Disconnected) {
+                    warn!(
+                        "Transceiver connection lost. Reconnecting in {} seconds",
+                        connection.retry_delay().as_secs()
+                    );
+                    drop(connection);
+                    // Free the port, the device may come back under the same name
+                    if let Err(error) = self.rig.lock().unwrap().close() {
+                        debug!("Failed to close the transceiver: {}", error);
+                    }
+                } else {
+                    drop(connection);
+                }
+                self.publish_connection_status(status);
+            }
+        }
+    }
+
+    fn try_reconnect(&self) {
+        let mut connection = self.connection.lock().unwrap();
+        if !connection.is_reconnect_due(Instant::now()) {
+            return;
+        }
+        let status = connection.reconnect_started();
+        drop(connection);
+        self.publish_connection_status(status);
+
+        info!("Reconnecting the transceiver");
+        match self.reopen_rig() {
+            Ok(caps) => {
+                info!("Transceiver reconnected");
+                *self.caps.lock().unwrap() = caps.clone();
+                *self.next_settings_poll.lock().unwrap() = Instant::now();
+                self.send_state_update(TransceiverStateMessage {
+                    subsystem: TransceiverSubsystem::Rig,
+                    parameter: TransceiverParameter::Capabilities {
+                        caps: Box::new(caps),
+                    },
+                });
+                let status = self.connection.lock().unwrap().reconnect_succeeded();
+                self.publish_connection_status(status);
+                self.poll_state();
+            }
+            Err(error) => {
+                let mut connection = self.connection.lock().unwrap();
+                let status = connection.reconnect_failed(Instant::now());
+                warn!(
+                    "Failed to reconnect the transceiver: {}. Retrying in {} seconds",
+                    error,
+                    connection.retry_delay().as_secs()
+                );
+                drop(connection);
+                self.publish_connection_status(status);
+            }
+        }
+    }
+
+    fn reopen_rig(&self) -> Result<RigCaps, TransceiverError> {
+        let mut rig = self.rig.lock().unwrap();
+        rig.reopen()?;
+        rig.caps()
+            .ok_or(TransceiverError::Hamlib(HamLibError::Internal))
+    }
+
+    /// Publishes the connection status after a state machine transition, if any.
+    fn publish_connection_status(&self, status: Option<TransceiverConnectionStatus>) {
+        let Some(status) = status else {
+            return;
+        };
+        info!("Transceiver connection status: {:?}", status);
+        self.state.lock().unwrap().connection = status;
+        self.send_state_update(TransceiverStateMessage {
+            subsystem: TransceiverSubsystem::Rig,
+            parameter: TransceiverParameter::Connection { status },
+        });
+    }
+
+    fn check_connected(&self) -> Result<(), TransceiverError> {
+        if self.connection.lock().unwrap().is_connected() {
+            Ok(())
+        } else {
+            Err(TransceiverError::Disconnected)
+        }
+    }
+
     pub fn get_caps(&self) -> RigCaps {
         self.caps.lock().unwrap().clone()
     }
@@ -227,6 +960,107 @@ impl TransceiverManager {
     }
 }
 
+fn read_vfo_state(rig: &Rig, vfo: u32) -> Result<TransceiverVfoState, TransceiverError> {
+    let freq = retry_on_timeout(|| rig.get_freq(vfo))?;
+    let (mode, passband) = retry_on_timeout(|| rig.get_mode(vfo))?;
+    let mode = TransceiverMode::from_hamlib_name(&mode)
+        .ok_or_else(|| TransceiverError::NotSupported(format!("hamlib mode {mode}")))?;
+    Ok(TransceiverVfoState {
+        freq: freq as u64,
+        mode: Some(mode),
+        passband_hz: passband.max(0) as u32,
+    })
+}
+
+/// Reads state some rigs can't report. Anything but a link failure reads as absent.
+fn optional_state<T>(
+    name: &str,
+    result: Result<T, TransceiverError>,
+) -> Result<Option<T>, TransceiverError> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(error) if !error.is_rig_failure() => {
+            trace!("Failed to read transceiver {}: {}", name, error);
+            Ok(None)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+/// Records the value of a rig setting, returns whether it changed.
+fn update_setting<K: PartialEq, V: PartialEq>(
+    settings: &mut Vec<(K, V)>,
+    key: K,
+    value: V,
+) -> bool {
+    match settings.iter_mut().find(|(setting, _)| *setting == key) {
+        Some((_, current)) if *current == value => false,
+        Some((_, current)) => {
+            *current = value;
+            true
+        }
+        None => {
+            settings.push((key, value));
+            true
+        }
+    }
+}
+
+fn check_offset(name: &str, offset_hz: i32, max_offset_hz: u32) -> Result<(), TransceiverError> {
+    if max_offset_hz == 0 {
+        return Err(TransceiverError::NotSupported(name.to_string()));
+    }
+    if offset_hz.unsigned_abs() > max_offset_hz {
+        return Err(TransceiverError::InvalidRequest(format!(
+            "{name} offset {offset_hz} Hz out of +/-{max_offset_hz} Hz"
+        )));
+    }
+    Ok(())
+}
+
+fn read_split_state(rig: &Rig) -> Result<TransceiverSplit, TransceiverError> {
+    let (enabled, tx_vfo) = retry_on_timeout(|| rig.get_split_vfo(CURRENT_VFO))?;
+    if !enabled {
+        return Ok(TransceiverSplit {
+            enabled,
+            tx_vfo,
+            ..TransceiverSplit::default()
+        });
+    }
+
+    let tx_freq = retry_on_timeout(|| rig.get_split_freq(CURRENT_VFO))?;
+    let (tx_mode, _) = retry_on_timeout(|| rig.get_split_mode(CURRENT_VFO))?;
+    Ok(TransceiverSplit {
+        enabled,
+        tx_vfo,
+        tx_freq: tx_freq as u64,
+        tx_mode: TransceiverMode::from_hamlib_name(&tx_mode),
+    })
+}
+
+/// Rigs without declared ranges accept any frequency, the rig rejects what it doesn't support.
+fn is_frequency_in_ranges(ranges: &[RigFrequencyRange], frequency: u64) -> bool {
+    ranges.is_empty()
+        || ranges.iter().any(|range| {
+            range.lower_frequency_hz <= frequency && frequency <= range.upper_frequency_hz
+        })
+}
+
+fn retry_on_timeout<T>(
+    mut command: impl FnMut() -> Result<T, HamLibError>,
+) -> Result<T, HamLibError> {
+    let mut retries = 0;
+    loop {
+        match command() {
+            Err(error) if error.is_timeout() && retries < COMMAND_TIMEOUT_RETRIES => {
+                retries += 1;
+                debug!("Transceiver command timed out, retry {}", retries);
+            }
+            result => return result,
+        }
+    }
+}
+
 impl From<ConfigHamlibDebugLevel> for RigDebugLevel {
     fn from(level: ConfigHamlibDebugLevel) -> Self {
         match level {
@@ -240,3 +1074,74 @@ impl From<ConfigHamlibDebugLevel> for RigDebugLevel {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{check_offset, is_frequency_in_ranges, optional_state, update_setting};
+    use crate::hardware::error::TransceiverError;
+    use hamlib::errors::HamLibError;
+    use hamlib::hamlib::RigFrequencyRange;
+
+    fn range(lower_frequency_hz: u64, upper_frequency_hz: u64) -> RigFrequencyRange {
+        RigFrequencyRange {
+            region: 1,
+            lower_frequency_hz,
+            upper_frequency_hz,
+            modes: vec![],
+            vfo: 0,
+            antenna: 0,
+            label: None,
+        }
+    }
+
+    #[test]
+    fn frequency_must_be_in_one_of_the_ranges() {
+        let ranges = vec![range(1_800_000, 2_000_000), range(7_000_000, 7_200_000)];
+
+        assert!(is_frequency_in_ranges(&ranges, 1_800_000));
+        assert!(is_frequency_in_ranges(&ranges, 7_200_000));
+        assert!(!is_frequency_in_ranges(&ranges, 3_500_000));
+    }
+
+    #[test]
+    fn rig_without_ranges_accepts_any_frequency() {
+        assert!(is_frequency_in_ranges(&[], 3_500_000));
+    }
+
+    #[test]
+    fn offset_must_be_within_the_rig_maximum() {
+        assert!(check_offset("RIT", -9_999, 9_999).is_ok());
+        assert!(matches!(
+            check_offset("RIT", 10_000, 9_999),
+            Err(TransceiverError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            check_offset("XIT", 0, 0),
+            Err(TransceiverError::NotSupported(_))
+        ));
+    }
+
+    #[test]
+    fn optional_state_is_absent_unless_the_link_failed() {
+        assert_eq!(optional_state("split", Ok(1)).unwrap(), Some(1));
+        for error in [
+            HamLibError::Rejected,
+            HamLibError::InvalidVfo,
+            HamLibError::Internal,
+        ] {
+            let result = optional_state::<i32>("split", Err(TransceiverError::from(error)));
+            assert_eq!(result.unwrap(), None);
+        }
+        assert!(optional_state::<i32>("split", Err(HamLibError::Timeout.into())).is_err());
+    }
+
+    #[test]
+    fn setting_update_reports_changes_only() {
+        let mut settings = vec![("AF", 0.5)];
+
+        assert!(!update_setting(&mut settings, "AF", 0.5));
+        assert!(update_setting(&mut settings, "AF", 0.8));
+        assert!(update_setting(&mut settings, "RF", 1.0));
+        assert_eq!(settings, vec![("AF", 0.8), ("RF", 1.0)]);
+    }
+}
@@ -13,18 +13,69 @@ You should have received a copy of the GNU General Public License
 along with this program. If not, see <https://www.gnu.org/licenses/>
  */
 
-use hamlib::hamlib::RigMode;
+use hamlib::hamlib::{RigCaps, RigMode};
+use hamlib::rig::{RigAntennas, RigFunction, RigLevel, RigPassband, RigPtt};
+use std::collections::BTreeMap;
 use std::fmt;
 
 pub type TransceiverMode = RigMode;
+pub type TransceiverPtt = RigPtt;
+pub type TransceiverLevel = RigLevel;
+pub type TransceiverFunction = RigFunction;
+pub type TransceiverPassband = RigPassband;
+pub type TransceiverAntennas = RigAntennas;
 
 #[derive(Clone)]
 pub struct TransceiverState {
-    pub main_vfo_freq: u64,
-    pub main_vfo_mode: Option<TransceiverMode>,
+    // Keyed by Hamlib VFO, 0 is the current VFO
+    pub vfos: BTreeMap<u32, TransceiverVfoState>,
+    pub ptt: TransceiverPtt,
+    // None when the rig can't report split operation
+    pub split: Option<TransceiverSplit>,
+    // RIT and XIT offsets in Hz, None when the rig has none
+    pub rit_hz: Option<i32>,
+    pub xit_hz: Option<i32>,
+    // Settable levels the rig can also read
+    pub levels: Vec<(TransceiverLevel, f32)>,
+    // Functions the rig can read, with their status
+    pub functions: Vec<(TransceiverFunction, bool)>,
+    // None when the rig can't report its antennas
+    pub antennas: Option<TransceiverAntennas>,
+    pub connection: TransceiverConnectionStatus,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TransceiverVfoState {
+    pub freq: u64,
+    pub mode: Option<TransceiverMode>,
+    pub passband_hz: u32,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TransceiverSplit {
+    pub enabled: bool,
+    pub tx_vfo: u32,
+    // Only read while split is enabled
+    pub tx_freq: u64,
+    pub tx_mode: Option<TransceiverMode>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransceiverMeterReading {
+    pub level: TransceiverLevel,
+    pub value: f32,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TransceiverConnectionStatus {
+    Connected,
+    // Last polls failed, the rig may be lost
+    Degraded,
+    Disconnected,
+    Reconnecting,
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct TransceiverStateMessage {
     pub subsystem: TransceiverSubsystem,
     pub parameter: TransceiverParameter,
@@ -32,21 +83,61 @@ pub struct TransceiverStateMessage {
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum TransceiverSubsystem {
-    Vfo { id: u8 },
+    Rig,
+    Vfo { id: u32 },
 }
 
 impl fmt::Display for TransceiverSubsystem {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Self::Rig => write!(f, "rig"),
             Self::Vfo { id } => write!(f, "vfo:{id}"),
         }
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum TransceiverParameter {
-    Frequency { freq: u64 },
-    Mode { mode: TransceiverMode },
+    Frequency {
+        freq: u64,
+    },
+    Mode {
+        mode: TransceiverMode,
+        passband_hz: u32,
+    },
+    Ptt {
+        ptt: TransceiverPtt,
+    },
+    Split {
+        split: TransceiverSplit,
+    },
+    Rit {
+        offset_hz: i32,
+    },
+    Xit {
+        offset_hz: i32,
+    },
+    Level {
+        level: TransceiverLevel,
+        value: f32,
+    },
+    Function {
+        function: TransceiverFunction,
+        enabled: bool,
+    },
+    Antennas {
+        antennas: TransceiverAntennas,
+    },
+    // Streamed at each meter poll, not part of the state
+    Meters {
+        readings: Vec<TransceiverMeterReading>,
+    },
+    Connection {
+        status: TransceiverConnectionStatus,
+    },
+    Capabilities {
+        caps: Box<RigCaps>,
+    },
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
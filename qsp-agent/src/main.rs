@@ -246,11 +246,18 @@ fn lock_file(path: &Path) -> Result<File, io::Error> {
 
 async fn start_server(config: Configuration) {
     let transceiver_manager =
-        hardware::transceiver::transceiver_manager::TransceiverManager::new(config.clone())
-            .unwrap();
-
-    let audio_session_manager = Arc::new(Mutex::new(AudioSessionManager::new()));
+        match hardware::transceiver::transceiver_manager::TransceiverManager::new(config.clone()) {
+            Ok(transceiver_manager) => transceiver_manager,
+            Err(error) => {
+                error!("Failed to initialize the transceiver: {}", error);
+                return;
+            }
+        };
+
+    let audio_session_manager =
+        Arc::new(Mutex::new(AudioSessionManager::new(config.audio.clone())));
     let webrtc_session_manager = Arc::new(WebrtcSessionManager::new(
+        config.clone(),
         audio_session_manager,
         transceiver_manager,
     ));
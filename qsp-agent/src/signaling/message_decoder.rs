@@ -17,6 +17,10 @@ use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 use tracing::error;
+use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
+use webrtc::ice_transport::ice_server::RTCIceServer;
+
+use crate::webrtc::webrtc_session::LocalIceCandidate;
 
 #[derive(Serialize, Deserialize)]
 #[serde(tag = "command")]
@@ -46,6 +50,8 @@ pub enum AgentSocketMessage {
         #[serde(rename = "exchangeId")]
         exchange_id: u32,
     },
+    #[serde(rename = "ICE_CANDIDATE")]
+    IceCandidateMessage { data: IceCandidatePayload },
 }
 
 #[derive(Serialize, Deserialize)]
@@ -59,6 +65,17 @@ pub struct ServerDescription {
     pub protocol_minor_version: i32,
     #[serde(rename = "serverName")]
     pub server_name: String,
+    #[serde(rename = "iceServers", default)]
+    pub ice_servers: Option<Vec<IceServerDescription>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct IceServerDescription {
+    pub urls: Vec<String>,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub credential: String,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -82,6 +99,10 @@ pub struct AgentDescription {
 #[derive(Serialize, Deserialize)]
 pub struct ClientInitPayload {
     pub sdp: String,
+    #[serde(rename = "iceServers", default)]
+    pub ice_servers: Option<Vec<IceServerDescription>>,
+    #[serde(rename = "trickleIce", default)]
+    pub trickle_ice: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -91,6 +112,55 @@ pub struct ClientInitResponsePayload {
     pub agent_session_uuid: Arc<String>,
 }
 
+/// ICE candidate of an agent session, in the browser `RTCIceCandidateInit` format.
+/// An empty candidate means the end of the candidates.
+#[derive(Serialize, Deserialize)]
+pub struct IceCandidatePayload {
+    #[serde(rename = "agentSessionUuid")]
+    pub agent_session_uuid: Arc<String>,
+    pub candidate: String,
+    #[serde(rename = "sdpMid", default)]
+    pub sdp_mid: Option<String>,
+    #[serde(rename = "sdpMLineIndex", default)]
+    pub sdp_mline_index: Option<u16>,
+    #[serde(rename = "usernameFragment", default)]
+    pub username_fragment: Option<String>,
+}
+
+impl From<LocalIceCandidate> for IceCandidatePayload {
+    fn from(local_candidate: LocalIceCandidate) -> Self {
+        let candidate = local_candidate.candidate.unwrap_or_default();
+        IceCandidatePayload {
+            agent_session_uuid: local_candidate.session_uuid,
+            candidate: candidate.candidate,
+            sdp_mid: candidate.sdp_mid,
+            sdp_mline_index: candidate.sdp_mline_index,
+            username_fragment: candidate.username_fragment,
+        }
+    }
+}
+
+impl From<IceCandidatePayload> for RTCIceCandidateInit {
+    fn from(payload: IceCandidatePayload) -> Self {
+        RTCIceCandidateInit {
+            candidate: payload.candidate,
+            sdp_mid: payload.sdp_mid,
+            sdp_mline_index: payload.sdp_mline_index,
+            username_fragment: payload.username_fragment,
+        }
+    }
+}
+
+impl From<IceServerDescription> for RTCIceServer {
+    fn from(ice_server: IceServerDescription) -> Self {
+        RTCIceServer {
+            urls: ice_server.urls,
+            username: ice_server.username,
+            credential: ice_server.credential,
+        }
+    }
+}
+
 pub fn decode_agent_message(message_str: String) -> serde_json::Result<AgentSocketMessage> {
     match serde_json::from_str(&message_str) {
         Ok(message) => Ok(message),
@@ -14,24 +14,33 @@ along with this program. If not, see <https://www.gnu.org/licenses/>
  */
 
 use anyhow::Result;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tracing::{debug, error, info};
+use webrtc::ice_transport::ice_server::RTCIceServer;
 
 use futures_util::{SinkExt, StreamExt};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
 use tokio::time::sleep;
 use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
 
 use crate::configuration::Configuration;
 use crate::signaling::message_decoder::{
     decode_agent_message, AgentDescription, AgentSocketMessage, ClientInitResponsePayload,
+    IceCandidatePayload, IceServerDescription,
 };
+use crate::webrtc::webrtc_session::LocalIceCandidate;
 use crate::webrtc::webrtc_session_manager::WebrtcSessionManager;
 use crate::{AGENT_TYPE_NAME, APPLICATION_VERSION};
 
 const PROTOCOL_VERSION_MAJOR: i32 = 0;
 const PROTOCOL_VERSION_MINOR: i32 = 1;
 
+/// `MESSAGE_ERROR` code for a command the agent doesn't handle.
+const ERROR_CODE_INVALID_COMMAND: u32 = 102;
+/// `MESSAGE_ERROR` code for a `CLIENT_INIT` the agent couldn't open a session for.
+const ERROR_CODE_SESSION_FAILED: u32 = 103;
+
 #[derive(thiserror::Error, Debug)]
 pub enum SignalingServerError {
     #[error("Can't connect the signaling server")]
@@ -44,6 +53,8 @@ pub enum SignalingServerError {
 
 pub struct SignalingServerSession {
     agent_description: Arc<AgentDescription>,
+    ice_servers: Mutex<Option<Vec<IceServerDescription>>>,
+    ice_candidate_sender: UnboundedSender<LocalIceCandidate>,
 }
 
 #[derive(Clone)]
@@ -114,33 +125,47 @@ impl SignalingServerManager {
         let (ws_stream, _) = connect_async(&url).await?;
         debug!("WebSocket connection established");
         let (mut write, mut read) = ws_stream.split();
+        let (ice_candidate_sender, mut ice_candidate_receiver) = unbounded_channel();
         let session = Arc::new(SignalingServerManager::create_session(
             self.agent_description.clone(),
+            ice_candidate_sender,
         ));
 
-        while let Some(message) = read.next().await {
-            let message = match message {
-                Ok(message) => message,
-                Err(err) => {
-                    error!("Error receiving message from signaling server: {}", err);
-                    return Ok(());
+        loop {
+            let tx_message = tokio::select! {
+                message = read.next() => {
+                    let Some(message) = message else {
+                        break;
+                    };
+                    let message = match message {
+                        Ok(message) => message,
+                        Err(err) => {
+                            error!("Error receiving message from signaling server: {}", err);
+                            return Ok(());
+                        }
+                    };
+                    debug!("Received message: {}", message);
+                    let message = match message.into_text() {
+                        Ok(message) => message,
+                        Err(err) => {
+                            error!("Error extracting signaling server message as text: {}", err);
+                            return Ok(());
+                        }
+                    };
+                    let msg = decode_agent_message(message.to_string())?;
+                    SignalingServerManager::process_message(
+                        self.webrtc_session_manager.clone(),
+                        session.clone(),
+                        msg,
+                    )
+                    .await?
                 }
-            };
-            debug!("Received message: {}", message);
-            let message = match message.into_text() {
-                Ok(message) => message,
-                Err(err) => {
-                    error!("Error extracting signaling server message as text: {}", err);
-                    return Ok(());
+                Some(candidate) = ice_candidate_receiver.recv() => {
+                    Some(AgentSocketMessage::IceCandidateMessage {
+                        data: IceCandidatePayload::from(candidate),
+                    })
                 }
             };
-            let msg = decode_agent_message(message.to_string())?;
-            let tx_message = SignalingServerManager::process_message(
-                self.webrtc_session_manager.clone(),
-                session.clone(),
-                msg,
-            )
-            .await?;
             if let Some(tx_message) = tx_message {
                 let tx_message_str = serde_json::to_string(&tx_message)?;
                 if let Err(err) = write.send(Message::Text(tx_message_str.into())).await {
@@ -153,8 +178,15 @@ impl SignalingServerManager {
         Ok(())
     }
 
-    fn create_session(agent_description: Arc<AgentDescription>) -> SignalingServerSession {
-        SignalingServerSession { agent_description }
+    fn create_session(
+        agent_description: Arc<AgentDescription>,
+        ice_candidate_sender: UnboundedSender<LocalIceCandidate>,
+    ) -> SignalingServerSession {
+        SignalingServerSession {
+            agent_description,
+            ice_servers: Mutex::new(None),
+            ice_candidate_sender,
+        }
     }
 
     fn retry_delay_for_failed_attempts(&self, failed_attempts: usize) -> Duration {
@@ -173,13 +205,39 @@ impl SignalingServerManager {
         match message {
             AgentSocketMessage::ServerHello { data } => {
                 info!("Got server hello. Server name is '{}'", data.server_name);
+                if let Some(ice_servers) = data.ice_servers {
+                    debug!("Server provided {} ICE servers", ice_servers.len());
+                    *session.ice_servers.lock().unwrap() = Some(ice_servers);
+                }
                 Ok(Some(AgentSocketMessage::AgentHello {
                     data: session.agent_description.clone(),
                 }))
             }
             AgentSocketMessage::ClientInitMessage { data, exchange_id } => {
                 info!("Received client init");
-                let (agent_sdp, uuid) = webrtc_session_manager.add_session(data.sdp).await?;
+                // Client init ICE servers take precedence over the server hello ones
+                let ice_servers = data
+                    .ice_servers
+                    .or_else(|| session.ice_servers.lock().unwrap().clone())
+                    .map(|ice_servers| ice_servers.into_iter().map(RTCIceServer::from).collect());
+                // Old servers don't relay ICE candidates, keep answering with a complete SDP
+                let ice_candidate_sender = data
+                    .trickle_ice
+                    .then(|| session.ice_candidate_sender.clone());
+                let (agent_sdp, uuid) = match webrtc_session_manager
+                    .add_session(data.sdp, ice_servers, ice_candidate_sender)
+                    .await
+                {
+                    Ok(session) => session,
+                    Err(err) => {
+                        error!("Failed to create webRTC session: {}", err);
+                        return Ok(Some(AgentSocketMessage::ErrorMessage {
+                            error_code: ERROR_CODE_SESSION_FAILED,
+                            error_message: format!("Agent failed to create session: {err}"),
+                            exchange_id: Some(exchange_id),
+                        }));
+                    }
+                };
                 debug!(
                     "Client init complete. Send client init response with uuid={}",
                     uuid
@@ -192,10 +250,24 @@ impl SignalingServerManager {
                     exchange_id,
                 }))
             }
+            AgentSocketMessage::IceCandidateMessage { data } => {
+                debug!(
+                    "Received remote ICE candidate for session {}",
+                    data.agent_session_uuid
+                );
+                let uuid = data.agent_session_uuid.clone();
+                if let Err(err) = webrtc_session_manager
+                    .add_ice_candidate(&uuid, data.into())
+                    .await
+                {
+                    error!("Failed to add ICE candidate to session {}: {}", uuid, err);
+                }
+                Ok(None)
+            }
             _ => {
                 info!("Received unexpected command type");
                 Ok(Some(AgentSocketMessage::ErrorMessage {
-                    error_code: 102,
+                    error_code: ERROR_CODE_INVALID_COMMAND,
                     error_message: "Agent received invalid command name".to_string(),
                     exchange_id: Some(0),
                 }))
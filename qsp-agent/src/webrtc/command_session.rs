@@ -12,56 +12,159 @@ GNU General Public License for more details.
 You should have received a copy of the GNU General Public License
 along with this program. If not, see <https://www.gnu.org/licenses/>
  */
+use crate::configuration::{AudioEncoderApplication, AudioEncoderProfile};
+use crate::hardware::audio_io::{
+    AudioDirection, AudioEncoderProfileError, AudioEvent, AudioSessionManager,
+};
+use crate::hardware::error::TransceiverError;
 use crate::hardware::transceiver::transceiver_manager::TransceiverManager;
 use crate::hardware::transceiver::transceiver_state::{
-    TransceiverMode, TransceiverParameter, TransceiverSubsystem,
+    TransceiverAntennas, TransceiverConnectionStatus, TransceiverFunction, TransceiverLevel,
+    TransceiverMeterReading, TransceiverMode, TransceiverParameter, TransceiverPtt,
+    TransceiverSplit, TransceiverSubsystem,
+};
+use crate::webrtc::operator_control::{
+    ControlRequestResult, OperatorControl, OperatorControlError, OperatorControlStatus,
 };
 use crate::webrtc::transceiver_mapping::{
-    band_to_transceiver_band, transceiver_mode_to_trx_vfo_mode, trx_vfo_mode_to_transceiver_mode,
+    band_to_transceiver_band, transceiver_connection_status_to_trx_connection_status,
+    transceiver_function_to_trx_function, transceiver_level_to_trx_level,
+    transceiver_level_to_trx_meter, transceiver_mode_to_trx_vfo_mode, transceiver_ptt_to_trx_ptt,
+    trx_function_to_transceiver_function, trx_level_to_transceiver_level,
+    trx_passband_to_transceiver_passband, trx_ptt_to_transceiver_ptt,
+    trx_vfo_mode_to_transceiver_mode,
 };
 use bytes::Bytes;
 use hamlib::hamlib::{RigCaps, RigFrequencyRange};
 use hamlib::rig::RigVfoOperation;
 use prost::Message;
 use qsp_proto_files::qsp::message::v1::agent_control_message::Message as AgentControlPayload;
+use qsp_proto_files::qsp::message::v1::agent_control_message::Message::Agent;
 use qsp_proto_files::qsp::message::v1::agent_control_message::Message::Transceiver;
 use qsp_proto_files::qsp::message::v1::agent_message::AgentMessage as AgentPayload;
 use qsp_proto_files::qsp::message::v1::transceiver_message::TransceiverMessage as TransceiverPayload;
 use qsp_proto_files::qsp::message::v1::transceiver_message::TransceiverMessage::FrequencyMessage;
 use qsp_proto_files::qsp::message::v1::transceiver_message::TransceiverMessage::ModeMessage;
 use qsp_proto_files::qsp::message::v1::{
-    AgentControlMessage, Band, RigFrequencyRangeMessage as ProtoRigFrequencyRange, TrxAntenna,
-    TrxCapabilitiesMessage, TrxFrequencyMessage, TrxModeMessage, TrxVfoFlag, TrxVfoMode,
-    TrxVfoOperation, TrxVfoOperationMessage,
+    AgentControlMessage, AudioApplication, AudioDeviceStatus,
+    AudioDirection as ProtoAudioDirection, AudioProfileMessage, AudioStatusMessage, Band,
+    CommandAckMessage, CommandErrorCategory, CommandErrorMessage, ControlStatusMessage,
+    RigFrequencyRangeMessage as ProtoRigFrequencyRange, TrxAntenna, TrxAntennaMessage,
+    TrxCapabilitiesMessage, TrxConnectionMessage, TrxFilterMessage, TrxFrequencyMessage,
+    TrxFunction, TrxFunctionMessage, TrxLevel, TrxLevelMessage, TrxLevelRangeMessage,
+    TrxMeterMessage, TrxMeterReading, TrxModeMessage, TrxPassband, TrxPtt, TrxPttMessage,
+    TrxRitMessage, TrxSplitMessage, TrxVfoFlag, TrxVfoMode, TrxVfoOperation,
+    TrxVfoOperationMessage, TrxXitMessage,
 };
-use std::sync::Arc;
-use tracing::{debug, error, warn};
+use std::sync::{Arc, Mutex};
+use tokio::task::AbortHandle;
+use tracing::{debug, error, trace, warn};
 use webrtc::data_channel::RTCDataChannel;
 
+#[derive(thiserror::Error, Debug)]
+enum CommandError {
+    #[error("received before hello handshake")]
+    BeforeHello,
+    #[error("session is not the operator")]
+    NotOperator,
+    #[error("{0}")]
+    InvalidRequest(String),
+    #[error(transparent)]
+    OperatorControl(#[from] OperatorControlError),
+    #[error(transparent)]
+    AudioProfile(#[from] AudioEncoderProfileError),
+    #[error(transparent)]
+    Transceiver(#[from] TransceiverError),
+}
+
+impl CommandError {
+    fn category(&self) -> CommandErrorCategory {
+        match self {
+            Self::BeforeHello | Self::InvalidRequest(_) | Self::AudioProfile(_) => {
+                CommandErrorCategory::InvalidRequest
+            }
+            Self::NotOperator | Self::OperatorControl(OperatorControlError::NotOperator) => {
+                CommandErrorCategory::NotOperator
+            }
+            Self::OperatorControl(OperatorControlError::NotRequested) => {
+                CommandErrorCategory::InvalidRequest
+            }
+            Self::Transceiver(TransceiverError::NotSupported(_)) => {
+                CommandErrorCategory::NotSupported
+            }
+            Self::Transceiver(
+                TransceiverError::InvalidRequest(_) | TransceiverError::Rejected(_),
+            ) => CommandErrorCategory::InvalidRequest,
+            Self::Transceiver(TransceiverError::Hamlib(error)) if error.is_timeout() => {
+                CommandErrorCategory::Timeout
+            }
+            Self::Transceiver(TransceiverError::Disconnected | TransceiverError::Hamlib(_)) => {
+                CommandErrorCategory::RigFailure
+            }
+        }
+    }
+
+    fn is_rig_failure(&self) -> bool {
+        matches!(self, Self::Transceiver(error) if error.is_rig_failure())
+    }
+}
+
 pub struct CommandSession {
     hello_done: bool,
     data_channel: Arc<RTCDataChannel>,
+    session_uuid: Arc<String>,
+    audio_session_manager: Arc<Mutex<AudioSessionManager>>,
     transceiver_manager: Arc<TransceiverManager>,
+    operator_control: Arc<OperatorControl>,
+    event_loops: Vec<AbortHandle>,
 }
 
 impl CommandSession {
     pub fn new(
         data_channel: Arc<RTCDataChannel>,
+        session_uuid: Arc<String>,
+        audio_session_manager: Arc<Mutex<AudioSessionManager>>,
         transceiver_manager: Arc<TransceiverManager>,
+        operator_control: Arc<OperatorControl>,
     ) -> Self {
         Self {
             hello_done: false,
             data_channel,
+            session_uuid,
+            audio_session_manager,
             transceiver_manager,
+            operator_control,
+            event_loops: vec![],
         }
     }
     pub fn command_received(&mut self, message: &AgentControlMessage) {
         let payload_type = CommandSession::agent_control_payload_type(message);
 
         debug!(
-            "AgentControlMessage from DataChannel 'payload={}'",
-            payload_type,
+            "AgentControlMessage from DataChannel 'payload={}' request_id={}",
+            payload_type, message.request_id,
         );
+        let result = self.process_command(message);
+        match &result {
+            Ok(()) => {}
+            Err(error) if error.is_rig_failure() => {
+                error!("AgentControlMessage '{}' failed: {}", payload_type, error)
+            }
+            Err(error) => warn!("AgentControlMessage '{}' rejected: {}", payload_type, error),
+        }
+
+        // Clients that don't number their commands only get the errors
+        if message.request_id != 0 || result.is_err() {
+            tokio::spawn(CommandSession::send_command_result(
+                self.data_channel.clone(),
+                message.request_id,
+                payload_type,
+                result,
+            ));
+        }
+    }
+
+    fn process_command(&mut self, message: &AgentControlMessage) -> Result<(), CommandError> {
         match message.message.as_ref() {
             Some(AgentControlPayload::Agent(agent_message)) => {
                 match agent_message.agent_message.as_ref() {
@@ -73,37 +176,118 @@ impl CommandSession {
                                 self.data_channel.clone(),
                                 self.transceiver_manager.get_caps(),
                             ));
-                            tokio::spawn(CommandSession::transceiver_event_loop(
-                                self.data_channel.clone(),
-                                self.transceiver_manager.clone(),
-                            ));
+                            let transceiver_event_loop =
+                                tokio::spawn(CommandSession::transceiver_event_loop(
+                                    self.data_channel.clone(),
+                                    self.transceiver_manager.clone(),
+                                ));
+                            let operator_control_event_loop =
+                                tokio::spawn(CommandSession::operator_control_event_loop(
+                                    self.data_channel.clone(),
+                                    self.session_uuid.clone(),
+                                    self.operator_control.clone(),
+                                    self.transceiver_manager.clone(),
+                                ));
+                            let audio_profile_event_loop =
+                                tokio::spawn(CommandSession::audio_profile_event_loop(
+                                    self.data_channel.clone(),
+                                    self.audio_session_manager.clone(),
+                                ));
+                            let audio_status_event_loop =
+                                tokio::spawn(CommandSession::audio_status_event_loop(
+                                    self.data_channel.clone(),
+                                    self.audio_session_manager.clone(),
+                                ));
+                            self.event_loops.push(transceiver_event_loop.abort_handle());
+                            self.event_loops
+                                .push(operator_control_event_loop.abort_handle());
+                            self.event_loops
+                                .push(audio_profile_event_loop.abort_handle());
+                            self.event_loops
+                                .push(audio_status_event_loop.abort_handle());
                         }
+                        Ok(())
                     }
-                    None => {
-                        error!("AgentControlMessage agent payload is empty");
-                    }
+                    Some(
+                        AgentPayload::ControlStatus(_)
+                        | AgentPayload::CommandAck(_)
+                        | AgentPayload::CommandError(_)
+                        | AgentPayload::AudioStatus(_),
+                    ) => Err(CommandError::InvalidRequest(
+                        "agent message received from DataChannel".to_string(),
+                    )),
+                    Some(payload) if self.hello_done => self.command_control_received(payload),
+                    Some(_) => Err(CommandError::BeforeHello),
+                    None => Err(CommandError::InvalidRequest(
+                        "agent payload is empty".to_string(),
+                    )),
                 }
             }
             Some(Transceiver(transceiver_message)) if self.hello_done => {
-                if let Some(payload) = transceiver_message.transceiver_message.as_ref() {
-                    self.command_transceiver_received(payload);
-                } else {
-                    error!("AgentControlMessage transceiver payload is empty");
+                let Some(payload) = transceiver_message.transceiver_message.as_ref() else {
+                    return Err(CommandError::InvalidRequest(
+                        "transceiver payload is empty".to_string(),
+                    ));
+                };
+                if !self.operator_control.touch(&self.session_uuid) {
+                    return Err(CommandError::NotOperator);
                 }
+                self.command_transceiver_received(payload)
             }
-            Some(_) => {
-                error!(
-                    "AgentControlMessage '{}' received before hello handshake",
-                    payload_type
+            Some(_) => Err(CommandError::BeforeHello),
+            None => Err(CommandError::InvalidRequest("payload is empty".to_string())),
+        }
+    }
+
+    fn command_control_received(&self, payload: &AgentPayload) -> Result<(), CommandError> {
+        match payload {
+            AgentPayload::ControlRequest(_) => {
+                match self.operator_control.request_control(&self.session_uuid) {
+                    ControlRequestResult::Granted => {
+                        debug!("Control granted to session {}", self.session_uuid)
+                    }
+                    ControlRequestResult::Pending => {
+                        debug!("Control request of session {} pending", self.session_uuid)
+                    }
+                }
+            }
+            AgentPayload::ControlRelease(_) => {
+                self.operator_control.release_control(&self.session_uuid);
+            }
+            AgentPayload::ControlHandover(handover) => {
+                debug!(
+                    "Control handover requested to session {}",
+                    handover.session_uuid
                 );
+                self.operator_control
+                    .handover_control(&self.session_uuid, &handover.session_uuid)?;
             }
-            None => {
-                error!("AgentControlMessage payload is empty");
+            AgentPayload::AudioProfile(profile) => {
+                // The audio stream is shared by all the sessions, only the operator changes it
+                if !self.operator_control.touch(&self.session_uuid) {
+                    return Err(CommandError::NotOperator);
+                }
+                let mut audio_session_manager = self.audio_session_manager.lock().unwrap();
+                let profile = audio_encoder_profile_from_audio_profile_message(
+                    profile,
+                    audio_session_manager.encoder_profile(),
+                );
+                debug!("Audio profile requested: {:?}", profile);
+                audio_session_manager.set_encoder_profile(profile)?;
             }
+            AgentPayload::Hello(_)
+            | AgentPayload::ControlStatus(_)
+            | AgentPayload::CommandAck(_)
+            | AgentPayload::CommandError(_)
+            | AgentPayload::AudioStatus(_) => {}
         }
+        Ok(())
     }
 
-    fn command_transceiver_received(&self, payload: &TransceiverPayload) {
+    fn command_transceiver_received(
+        &self,
+        payload: &TransceiverPayload,
+    ) -> Result<(), CommandError> {
         match payload {
             TransceiverPayload::FrequencyMessage(frequency) => {
                 debug!(
@@ -111,76 +295,184 @@ impl CommandSession {
                     frequency.vfo_id, frequency.frequency
                 );
                 self.transceiver_manager
-                    .set_frequency(frequency.vfo_id, frequency.frequency);
+                    .set_frequency(frequency.vfo_id, frequency.frequency)?;
             }
             TransceiverPayload::ModeMessage(mode) => {
-                let Some(transceiver_mode) = TrxVfoMode::try_from(mode.mode)
+                let transceiver_mode = TrxVfoMode::try_from(mode.mode)
                     .ok()
                     .and_then(trx_vfo_mode_to_transceiver_mode)
-                else {
-                    error!("Unsupported mode command received: {}", mode.mode);
-                    return;
-                };
+                    .ok_or_else(|| {
+                        CommandError::InvalidRequest(format!("unsupported mode {}", mode.mode))
+                    })?;
+                let passband = trx_passband_to_transceiver_passband(
+                    TrxPassband::try_from(mode.passband).unwrap_or(TrxPassband::Unspecified),
+                    mode.passband_hz,
+                );
 
                 debug!(
-                    "Mode command received for VFO {}: {:?}",
-                    mode.vfo_id, transceiver_mode
+                    "Mode command received for VFO {}: {:?} {:?}",
+                    mode.vfo_id, transceiver_mode, passband
                 );
-                if let Err(error) = self
-                    .transceiver_manager
-                    .set_mode(mode.vfo_id, transceiver_mode)
-                {
-                    error!(
-                        "Failed to set VFO {} mode to {:?}: {}",
-                        mode.vfo_id, transceiver_mode, error.message
-                    );
-                }
+                self.transceiver_manager
+                    .set_mode(mode.vfo_id, transceiver_mode, passband)?;
             }
             TransceiverPayload::BandMessage(band) => {
-                let Some(transceiver_band) = Band::try_from(band.band)
+                let transceiver_band = Band::try_from(band.band)
                     .ok()
                     .and_then(band_to_transceiver_band)
-                else {
-                    error!("Unsupported band command received: {}", band.band);
-                    return;
-                };
+                    .ok_or_else(|| {
+                        CommandError::InvalidRequest(format!("unsupported band {}", band.band))
+                    })?;
 
                 debug!(
                     "Band command received for VFO {}: {:?}",
                     band.vfo_id, transceiver_band
                 );
-                if let Err(error) = self.transceiver_manager.set_band(transceiver_band) {
-                    error!(
-                        "Failed to set transceiver band to {:?}: {}",
-                        transceiver_band, error.message
-                    );
+                self.transceiver_manager.set_band(transceiver_band)?;
+            }
+            TransceiverPayload::TrxPttMessage(ptt) => {
+                let transceiver_ptt = TrxPtt::try_from(ptt.ptt)
+                    .ok()
+                    .and_then(trx_ptt_to_transceiver_ptt)
+                    .ok_or_else(|| {
+                        CommandError::InvalidRequest(format!("unsupported PTT {}", ptt.ptt))
+                    })?;
+
+                debug!(
+                    "PTT command received for VFO {}: {:?}",
+                    ptt.vfo_id, transceiver_ptt
+                );
+                self.transceiver_manager.set_ptt(
+                    &self.session_uuid,
+                    ptt.vfo_id,
+                    transceiver_ptt,
+                )?;
+            }
+            TransceiverPayload::TrxSplitMessage(split) => {
+                let tx_mode = TrxVfoMode::try_from(split.tx_mode).map_err(|_| {
+                    CommandError::InvalidRequest(format!(
+                        "unsupported split mode {}",
+                        split.tx_mode
+                    ))
+                })?;
+                let tx_mode = trx_vfo_mode_to_transceiver_mode(tx_mode);
+
+                debug!(
+                    "Split command received for VFO {}: enabled {}, TX VFO {}, TX frequency {}, TX mode {:?}",
+                    split.vfo_id, split.enabled, split.tx_vfo_id, split.tx_frequency, tx_mode
+                );
+                // A zero TX frequency or an unspecified TX mode keeps the current one
+                let tx_frequency =
+                    Some(split.tx_frequency).filter(|frequency| split.enabled && *frequency != 0);
+                let tx_mode = tx_mode.filter(|_| split.enabled);
+                // Refuse an invalid TX side before enabling split on the rig
+                self.transceiver_manager
+                    .check_split_transmit(tx_frequency, tx_mode)?;
+                self.transceiver_manager
+                    .set_split(split.vfo_id, split.enabled, split.tx_vfo_id)?;
+                if let Some(tx_frequency) = tx_frequency {
+                    self.transceiver_manager
+                        .set_split_frequency(split.vfo_id, tx_frequency)?;
                 }
+                if let Some(tx_mode) = tx_mode {
+                    self.transceiver_manager
+                        .set_split_mode(split.vfo_id, tx_mode)?;
+                }
+            }
+            TransceiverPayload::TrxRitMessage(rit) => {
+                debug!(
+                    "RIT command received for VFO {}: {} Hz",
+                    rit.vfo_id, rit.offset_hz
+                );
+                self.transceiver_manager
+                    .set_rit(rit.vfo_id, rit.offset_hz)?;
+            }
+            TransceiverPayload::TrxXitMessage(xit) => {
+                debug!(
+                    "XIT command received for VFO {}: {} Hz",
+                    xit.vfo_id, xit.offset_hz
+                );
+                self.transceiver_manager
+                    .set_xit(xit.vfo_id, xit.offset_hz)?;
             }
-            TransceiverPayload::TrxCapabilitiesMessage(_) => {
-                warn!("Transceiver capabilities message received from DataChannel");
+            TransceiverPayload::TrxLevelMessage(level) => {
+                let transceiver_level = TrxLevel::try_from(level.level)
+                    .ok()
+                    .and_then(trx_level_to_transceiver_level)
+                    .ok_or_else(|| {
+                        CommandError::InvalidRequest(format!("unsupported level {}", level.level))
+                    })?;
+
+                debug!(
+                    "Level command received: {:?} {}",
+                    transceiver_level, level.value
+                );
+                self.transceiver_manager
+                    .set_level(transceiver_level, level.value)?;
+            }
+            TransceiverPayload::TrxFunctionMessage(function) => {
+                let transceiver_function = TrxFunction::try_from(function.function)
+                    .ok()
+                    .and_then(trx_function_to_transceiver_function)
+                    .ok_or_else(|| {
+                        CommandError::InvalidRequest(format!(
+                            "unsupported function {}",
+                            function.function
+                        ))
+                    })?;
+
+                debug!(
+                    "Function command received: {:?} {}",
+                    transceiver_function, function.enabled
+                );
+                self.transceiver_manager
+                    .set_function(transceiver_function, function.enabled)?;
+            }
+            TransceiverPayload::TrxAntennaMessage(antenna) => {
+                let hamlib_antenna = TrxAntenna::try_from(antenna.antenna)
+                    .ok()
+                    .and_then(hamlib_antenna_from_trx_antenna)
+                    .ok_or_else(|| {
+                        CommandError::InvalidRequest(format!(
+                            "unsupported antenna {}",
+                            antenna.antenna
+                        ))
+                    })?;
+
+                debug!(
+                    "Antenna command received for VFO {}: 0x{:08x}, option {}",
+                    antenna.vfo_id, hamlib_antenna, antenna.option
+                );
+                self.transceiver_manager.set_antenna(
+                    antenna.vfo_id,
+                    hamlib_antenna,
+                    antenna.option,
+                )?;
+            }
+            TransceiverPayload::TrxCapabilitiesMessage(_)
+            | TransceiverPayload::TrxConnectionMessage(_)
+            | TransceiverPayload::TrxMeterMessage(_) => {
+                return Err(CommandError::InvalidRequest(
+                    "transceiver state message received from DataChannel".to_string(),
+                ));
             }
             TransceiverPayload::TrxVfoMessage(vfo_operation) => {
-                let Some(operation) = rig_vfo_operation_from_trx_vfo_operation(vfo_operation)
-                else {
-                    error!("Unsupported or missing VFO operation command received");
-                    return;
-                };
+                let operation = rig_vfo_operation_from_trx_vfo_operation(vfo_operation)
+                    .ok_or_else(|| {
+                        CommandError::InvalidRequest(
+                            "unsupported or missing VFO operation".to_string(),
+                        )
+                    })?;
 
                 debug!(
                     "VFO operation command received for VFO {}: {:?}",
                     vfo_operation.vfo_id, operation
                 );
-                if let Err(error) = self
-                    .transceiver_manager
-                    .vfo_operation(vfo_operation.vfo_id, operation)
-                {
-                    error!(
-                        "Failed to run VFO {} operation {:?}: {}",
-                        vfo_operation.vfo_id, operation, error.message
-                    );
-                }
+                self.transceiver_manager
+                    .vfo_operation(vfo_operation.vfo_id, operation)?;
             }
         }
+        Ok(())
     }
 
     fn agent_control_payload_type(message: &AgentControlMessage) -> &'static str {
@@ -188,6 +480,14 @@ impl CommandSession {
             Some(AgentControlPayload::Agent(agent_message)) => {
                 match agent_message.agent_message.as_ref() {
                     Some(AgentPayload::Hello(_)) => "hello",
+                    Some(AgentPayload::ControlRequest(_)) => "control_request",
+                    Some(AgentPayload::ControlRelease(_)) => "control_release",
+                    Some(AgentPayload::ControlHandover(_)) => "control_handover",
+                    Some(AgentPayload::ControlStatus(_)) => "control_status",
+                    Some(AgentPayload::CommandAck(_)) => "command_ack",
+                    Some(AgentPayload::CommandError(_)) => "command_error",
+                    Some(AgentPayload::AudioProfile(_)) => "audio_profile",
+                    Some(AgentPayload::AudioStatus(_)) => "audio_status",
                     None => "agent_empty",
                 }
             }
@@ -197,8 +497,17 @@ impl CommandSession {
                     Some(FrequencyMessage(_)) => "frequency",
                     Some(ModeMessage(_)) => "mode",
                     Some(TransceiverPayload::BandMessage(_)) => "band",
+                    Some(TransceiverPayload::TrxPttMessage(_)) => "ptt",
                     Some(TransceiverPayload::TrxCapabilitiesMessage(_)) => "capabilities",
+                    Some(TransceiverPayload::TrxConnectionMessage(_)) => "connection",
                     Some(TransceiverPayload::TrxVfoMessage(_)) => "trx_vfo",
+                    Some(TransceiverPayload::TrxSplitMessage(_)) => "split",
+                    Some(TransceiverPayload::TrxRitMessage(_)) => "rit",
+                    Some(TransceiverPayload::TrxXitMessage(_)) => "xit",
+                    Some(TransceiverPayload::TrxMeterMessage(_)) => "meter",
+                    Some(TransceiverPayload::TrxLevelMessage(_)) => "level",
+                    Some(TransceiverPayload::TrxFunctionMessage(_)) => "function",
+                    Some(TransceiverPayload::TrxAntennaMessage(_)) => "antenna",
                 }
             }
             None => "none",
@@ -207,6 +516,7 @@ impl CommandSession {
 
     async fn send_transceiver_caps(data_channel: Arc<RTCDataChannel>, caps: RigCaps) {
         let message = AgentControlMessage {
+            request_id: 0,
             message: Some(Transceiver(
                 qsp_proto_files::qsp::message::v1::TransceiverMessage {
                     transceiver_message: Some(TransceiverPayload::TrxCapabilitiesMessage(
@@ -223,6 +533,37 @@ impl CommandSession {
         }
     }
 
+    /// Acknowledges the command, or tells the client that sent it why it was rejected.
+    async fn send_command_result(
+        data_channel: Arc<RTCDataChannel>,
+        request_id: u32,
+        command: &'static str,
+        result: Result<(), CommandError>,
+    ) {
+        let payload = match &result {
+            Ok(()) => AgentPayload::CommandAck(CommandAckMessage {
+                command: command.to_string(),
+            }),
+            Err(error) => AgentPayload::CommandError(CommandErrorMessage {
+                command: command.to_string(),
+                category: error.category() as i32,
+                message: error.to_string(),
+            }),
+        };
+        let message = AgentControlMessage {
+            request_id,
+            message: Some(Agent(qsp_proto_files::qsp::message::v1::AgentMessage {
+                agent_message: Some(payload),
+            })),
+        };
+
+        let bytes = Bytes::from(message.encode_to_vec());
+        match data_channel.send(&bytes).await {
+            Ok(_) => debug!("Sent {command} command result to DataChannel: {result:?}"),
+            Err(error) => error!("Failed to send {command} command result to DataChannel: {error}"),
+        }
+    }
+
     async fn transceiver_event_loop(
         data_channel: Arc<RTCDataChannel>,
         transceiver_manager: Arc<TransceiverManager>,
@@ -235,12 +576,113 @@ impl CommandSession {
                 TransceiverParameter::Frequency { freq } => {
                     evt_freq_updated(freq, message.subsystem, Arc::clone(&data_channel)).await
                 }
-                TransceiverParameter::Mode { mode } => {
-                    evt_mode_updated(mode, message.subsystem, Arc::clone(&data_channel)).await
+                TransceiverParameter::Mode { mode, passband_hz } => {
+                    evt_mode_updated(
+                        mode,
+                        passband_hz,
+                        message.subsystem,
+                        Arc::clone(&data_channel),
+                    )
+                    .await
+                }
+                TransceiverParameter::Ptt { ptt } => {
+                    evt_ptt_updated(ptt, message.subsystem, Arc::clone(&data_channel)).await
+                }
+                TransceiverParameter::Split { split } => {
+                    evt_split_updated(split, message.subsystem, Arc::clone(&data_channel)).await
+                }
+                TransceiverParameter::Rit { offset_hz } => {
+                    evt_rit_updated(offset_hz, message.subsystem, Arc::clone(&data_channel)).await
+                }
+                TransceiverParameter::Xit { offset_hz } => {
+                    evt_xit_updated(offset_hz, message.subsystem, Arc::clone(&data_channel)).await
+                }
+                TransceiverParameter::Level { level, value } => {
+                    evt_level_updated(level, value, Arc::clone(&data_channel)).await
+                }
+                TransceiverParameter::Function { function, enabled } => {
+                    evt_function_updated(function, enabled, Arc::clone(&data_channel)).await
+                }
+                TransceiverParameter::Antennas { antennas } => {
+                    evt_antennas_updated(antennas, message.subsystem, Arc::clone(&data_channel))
+                        .await
+                }
+                TransceiverParameter::Meters { readings } => {
+                    evt_meters_updated(readings, Arc::clone(&data_channel)).await
+                }
+                TransceiverParameter::Connection { status } => {
+                    evt_connection_updated(status, Arc::clone(&data_channel)).await
+                }
+                TransceiverParameter::Capabilities { caps } => {
+                    // The rig was reopened, clients refresh what it supports
+                    CommandSession::send_transceiver_caps(Arc::clone(&data_channel), *caps).await
                 }
             }
         }
     }
+
+    async fn operator_control_event_loop(
+        data_channel: Arc<RTCDataChannel>,
+        session_uuid: Arc<String>,
+        operator_control: Arc<OperatorControl>,
+        transceiver_manager: Arc<TransceiverManager>,
+    ) {
+        debug!("CommandSession operator control event loop started");
+        let mut receiver = operator_control.add_status_receiver();
+        evt_control_status_updated(
+            operator_control.status(),
+            &session_uuid,
+            Arc::clone(&data_channel),
+        )
+        .await;
+        while let Some(status) = receiver.recv().await {
+            if status.operator_session_uuid.as_ref() != Some(&session_uuid) {
+                // Lost the control, TX rights go with it
+                transceiver_manager.release_ptt(&session_uuid);
+            }
+            evt_control_status_updated(status, &session_uuid, Arc::clone(&data_channel)).await;
+        }
+    }
+
+    async fn audio_profile_event_loop(
+        data_channel: Arc<RTCDataChannel>,
+        audio_session_manager: Arc<Mutex<AudioSessionManager>>,
+    ) {
+        debug!("CommandSession audio profile event loop started");
+        let (mut receiver, profile) = {
+            let mut audio_session_manager = audio_session_manager.lock().unwrap();
+            (
+                audio_session_manager.add_encoder_profile_receiver(),
+                audio_session_manager.encoder_profile(),
+            )
+        };
+        evt_audio_profile_updated(profile, Arc::clone(&data_channel)).await;
+        while let Some(profile) = receiver.recv().await {
+            evt_audio_profile_updated(profile, Arc::clone(&data_channel)).await;
+        }
+    }
+
+    async fn audio_status_event_loop(
+        data_channel: Arc<RTCDataChannel>,
+        audio_session_manager: Arc<Mutex<AudioSessionManager>>,
+    ) {
+        debug!("CommandSession audio status event loop started");
+        let mut receiver = audio_session_manager.lock().unwrap().add_event_receiver();
+        while let Some(event) = receiver.recv().await {
+            // Dropped frames are only logged, the stream goes on
+            if let Some(status) = audio_status_message_from_audio_event(event) {
+                evt_audio_status_updated(status, Arc::clone(&data_channel)).await;
+            }
+        }
+    }
+}
+
+impl Drop for CommandSession {
+    fn drop(&mut self) {
+        for event_loop in &self.event_loops {
+            event_loop.abort();
+        }
+    }
 }
 
 fn trx_capabilities_from_rig_caps(caps: RigCaps) -> TrxCapabilitiesMessage {
@@ -248,6 +690,48 @@ fn trx_capabilities_from_rig_caps(caps: RigCaps) -> TrxCapabilitiesMessage {
         rig_model: caps.rig_model,
         model_name: caps.model_name,
         manufacturer_name: caps.manufacturer_name,
+        max_rit_hz: caps.max_rit_hz,
+        max_xit_hz: caps.max_xit_hz,
+        meters: caps
+            .get_levels
+            .into_iter()
+            .filter_map(transceiver_level_to_trx_meter)
+            .map(|meter| meter as i32)
+            .collect(),
+        levels: caps
+            .set_levels
+            .into_iter()
+            .filter_map(|range| {
+                transceiver_level_to_trx_level(range.level).map(|level| TrxLevelRangeMessage {
+                    level: level as i32,
+                    min: range.min,
+                    max: range.max,
+                    step: range.step,
+                })
+            })
+            .collect(),
+        filters: caps
+            .filters
+            .into_iter()
+            .map(|filter| TrxFilterMessage {
+                modes: filter
+                    .modes
+                    .into_iter()
+                    .map(|mode| transceiver_mode_to_trx_vfo_mode(mode) as i32)
+                    .collect(),
+                width_hz: filter.width_hz,
+            })
+            .collect(),
+        get_functions: caps
+            .get_functions
+            .into_iter()
+            .map(|function| transceiver_function_to_trx_function(function) as i32)
+            .collect(),
+        set_functions: caps
+            .set_functions
+            .into_iter()
+            .map(|function| transceiver_function_to_trx_function(function) as i32)
+            .collect(),
         rx_frequency_ranges: caps
             .rx_frequency_ranges
             .into_iter()
@@ -289,18 +773,130 @@ fn rig_vfo_operation_from_trx_vfo_operation(
     }
 }
 
-fn rig_antennas_from_hamlib_antenna(antenna: u32) -> Vec<i32> {
-    const HAMLIB_ANTENNA_FLAGS: &[(u32, TrxAntenna)] = &[
-        (1u32 << 0, TrxAntenna::TrxAntenna1),
-        (1u32 << 1, TrxAntenna::TrxAntenna2),
-        (1u32 << 2, TrxAntenna::TrxAntenna3),
-        (1u32 << 3, TrxAntenna::TrxAntenna4),
-        (1u32 << 4, TrxAntenna::TrxAntenna5),
-        (1u32 << 5, TrxAntenna::TrxAntenna6),
-        (1u32 << 6, TrxAntenna::TrxAntenna7),
-        (1u32 << 7, TrxAntenna::TrxAntenna8),
-    ];
+/// Fields left unspecified in the request keep their current value. A bitrate of 0 lets the
+/// encoder choose it.
+fn audio_encoder_profile_from_audio_profile_message(
+    message: &AudioProfileMessage,
+    current: AudioEncoderProfile,
+) -> AudioEncoderProfile {
+    let application = match AudioApplication::try_from(message.application) {
+        Ok(AudioApplication::Voip) => AudioEncoderApplication::Voip,
+        Ok(AudioApplication::Audio) => AudioEncoderApplication::Audio,
+        Ok(AudioApplication::LowDelay) => AudioEncoderApplication::LowDelay,
+        Ok(AudioApplication::Unspecified) | Err(_) => current.application,
+    };
+    AudioEncoderProfile {
+        application,
+        bitrate: match message.bitrate {
+            Some(0) => None,
+            Some(bitrate) => Some(bitrate.min(i32::MAX as u32) as i32),
+            None => current.bitrate,
+        },
+        frame_duration_ms: if message.frame_duration_ms == 0 {
+            current.frame_duration_ms
+        } else {
+            message.frame_duration_ms
+        },
+        inband_fec: message.inband_fec.unwrap_or(current.inband_fec),
+        dtx: message.dtx.unwrap_or(current.dtx),
+        packet_loss_percent: message
+            .packet_loss_percent
+            .map_or(current.packet_loss_percent, |percent| {
+                percent.min(u8::MAX as u32) as u8
+            }),
+    }
+}
+
+fn audio_profile_message_from_audio_encoder_profile(
+    profile: AudioEncoderProfile,
+) -> AudioProfileMessage {
+    let application = match profile.application {
+        AudioEncoderApplication::Voip => AudioApplication::Voip,
+        AudioEncoderApplication::Audio => AudioApplication::Audio,
+        AudioEncoderApplication::LowDelay => AudioApplication::LowDelay,
+    };
+    AudioProfileMessage {
+        application: application as i32,
+        bitrate: Some(profile.bitrate.unwrap_or_default() as u32),
+        frame_duration_ms: profile.frame_duration_ms,
+        inband_fec: Some(profile.inband_fec),
+        dtx: Some(profile.dtx),
+        packet_loss_percent: Some(profile.packet_loss_percent as u32),
+    }
+}
+
+const HAMLIB_ANTENNA_FLAGS: &[(u32, TrxAntenna)] = &[
+    (1u32 << 0, TrxAntenna::TrxAntenna1),
+    (1u32 << 1, TrxAntenna::TrxAntenna2),
+    (1u32 << 2, TrxAntenna::TrxAntenna3),
+    (1u32 << 3, TrxAntenna::TrxAntenna4),
+    (1u32 << 4, TrxAntenna::TrxAntenna5),
+    (1u32 << 5, TrxAntenna::TrxAntenna6),
+    (1u32 << 6, TrxAntenna::TrxAntenna7),
+    (1u32 << 7, TrxAntenna::TrxAntenna8),
+];
 
+fn audio_status_message_from_audio_event(event: AudioEvent) -> Option<AudioStatusMessage> {
+    let (direction, status) = match event {
+        AudioEvent::Started {
+            direction,
+            device_name,
+        } => (
+            direction,
+            AudioStatusMessage {
+                status: AudioDeviceStatus::Started as i32,
+                device_name,
+                ..Default::default()
+            },
+        ),
+        AudioEvent::Failed {
+            direction,
+            error,
+            retry_in,
+        } => (
+            direction,
+            AudioStatusMessage {
+                status: AudioDeviceStatus::Failed as i32,
+                error: error.to_string(),
+                retry_in_seconds: retry_in.as_secs() as u32,
+                ..Default::default()
+            },
+        ),
+        AudioEvent::Stopped { direction } => (
+            direction,
+            AudioStatusMessage {
+                status: AudioDeviceStatus::Stopped as i32,
+                ..Default::default()
+            },
+        ),
+        AudioEvent::FrameDropped { .. } => return None,
+    };
+    let direction = match direction {
+        AudioDirection::Input => ProtoAudioDirection::Input,
+        AudioDirection::Output => ProtoAudioDirection::Output,
+    };
+    Some(AudioStatusMessage {
+        direction: direction as i32,
+        ..status
+    })
+}
+
+fn hamlib_antenna_from_trx_antenna(antenna: TrxAntenna) -> Option<u32> {
+    HAMLIB_ANTENNA_FLAGS
+        .iter()
+        .find(|(_, antenna_flag)| *antenna_flag == antenna)
+        .map(|(bit, _)| *bit)
+}
+
+fn trx_antenna_from_hamlib_antenna(antenna: u32) -> TrxAntenna {
+    HAMLIB_ANTENNA_FLAGS
+        .iter()
+        .find(|(bit, _)| *bit == antenna)
+        .map(|(_, antenna_flag)| *antenna_flag)
+        .unwrap_or(TrxAntenna::Unspecified)
+}
+
+fn rig_antennas_from_hamlib_antenna(antenna: u32) -> Vec<i32> {
     if antenna == 0 {
         return vec![TrxAntenna::Unspecified as i32];
     }
@@ -377,10 +973,11 @@ async fn evt_freq_updated(
     match transceiver_subsystem {
         TransceiverSubsystem::Vfo { id } => {
             let message = AgentControlMessage {
+                request_id: 0,
                 message: Some(Transceiver(
                     qsp_proto_files::qsp::message::v1::TransceiverMessage {
                         transceiver_message: Some(FrequencyMessage(TrxFrequencyMessage {
-                            vfo_id: id as u32,
+                            vfo_id: id,
                             frequency: freq,
                         })),
                     },
@@ -395,11 +992,13 @@ async fn evt_freq_updated(
                 }
             }
         }
+        TransceiverSubsystem::Rig => warn!("Frequency update without VFO ignored"),
     }
 }
 
 async fn evt_mode_updated(
     mode: TransceiverMode,
+    passband_hz: u32,
     transceiver_subsystem: TransceiverSubsystem,
     data_channel: Arc<RTCDataChannel>,
 ) {
@@ -408,11 +1007,14 @@ async fn evt_mode_updated(
             let mode_value = transceiver_mode_to_trx_vfo_mode(mode);
 
             let message = AgentControlMessage {
+                request_id: 0,
                 message: Some(Transceiver(
                     qsp_proto_files::qsp::message::v1::TransceiverMessage {
                         transceiver_message: Some(ModeMessage(TrxModeMessage {
-                            vfo_id: id as u32,
+                            vfo_id: id,
                             mode: mode_value as i32,
+                            passband: TrxPassband::Unspecified as i32,
+                            passband_hz,
                         })),
                     },
                 )),
@@ -426,5 +1028,438 @@ async fn evt_mode_updated(
                 }
             }
         }
+        TransceiverSubsystem::Rig => warn!("Mode update without VFO ignored"),
+    }
+}
+
+async fn evt_split_updated(
+    split: TransceiverSplit,
+    transceiver_subsystem: TransceiverSubsystem,
+    data_channel: Arc<RTCDataChannel>,
+) {
+    match transceiver_subsystem {
+        TransceiverSubsystem::Vfo { id } => {
+            let tx_mode = split
+                .tx_mode
+                .map(transceiver_mode_to_trx_vfo_mode)
+                .unwrap_or(TrxVfoMode::Unspecified);
+
+            let message = AgentControlMessage {
+                request_id: 0,
+                message: Some(Transceiver(
+                    qsp_proto_files::qsp::message::v1::TransceiverMessage {
+                        transceiver_message: Some(TransceiverPayload::TrxSplitMessage(
+                            TrxSplitMessage {
+                                vfo_id: id,
+                                enabled: split.enabled,
+                                tx_vfo_id: split.tx_vfo,
+                                tx_frequency: split.tx_freq,
+                                tx_mode: tx_mode as i32,
+                            },
+                        )),
+                    },
+                )),
+            };
+
+            let bytes = Bytes::from(message.encode_to_vec());
+            match data_channel.send(&bytes).await {
+                Ok(_) => debug!("Sent VFO {id} split update to DataChannel: {split:?}"),
+                Err(error) => {
+                    error!("Failed to send VFO {id} split update to DataChannel: {error}")
+                }
+            }
+        }
+        TransceiverSubsystem::Rig => warn!("Split update without VFO ignored"),
+    }
+}
+
+async fn evt_rit_updated(
+    offset_hz: i32,
+    transceiver_subsystem: TransceiverSubsystem,
+    data_channel: Arc<RTCDataChannel>,
+) {
+    match transceiver_subsystem {
+        TransceiverSubsystem::Vfo { id } => {
+            let message = AgentControlMessage {
+                request_id: 0,
+                message: Some(Transceiver(
+                    qsp_proto_files::qsp::message::v1::TransceiverMessage {
+                        transceiver_message: Some(TransceiverPayload::TrxRitMessage(
+                            TrxRitMessage {
+                                vfo_id: id,
+                                offset_hz,
+                            },
+                        )),
+                    },
+                )),
+            };
+
+            let bytes = Bytes::from(message.encode_to_vec());
+            match data_channel.send(&bytes).await {
+                Ok(_) => debug!("Sent VFO {id} RIT update to DataChannel: {offset_hz}"),
+                Err(error) => {
+                    error!("Failed to send VFO {id} RIT update to DataChannel: {error}")
+                }
+            }
+        }
+        TransceiverSubsystem::Rig => warn!("RIT update without VFO ignored"),
+    }
+}
+
+async fn evt_xit_updated(
+    offset_hz: i32,
+    transceiver_subsystem: TransceiverSubsystem,
+    data_channel: Arc<RTCDataChannel>,
+) {
+    match transceiver_subsystem {
+        TransceiverSubsystem::Vfo { id } => {
+            let message = AgentControlMessage {
+                request_id: 0,
+                message: Some(Transceiver(
+                    qsp_proto_files::qsp::message::v1::TransceiverMessage {
+                        transceiver_message: Some(TransceiverPayload::TrxXitMessage(
+                            TrxXitMessage {
+                                vfo_id: id,
+                                offset_hz,
+                            },
+                        )),
+                    },
+                )),
+            };
+
+            let bytes = Bytes::from(message.encode_to_vec());
+            match data_channel.send(&bytes).await {
+                Ok(_) => debug!("Sent VFO {id} XIT update to DataChannel: {offset_hz}"),
+                Err(error) => {
+                    error!("Failed to send VFO {id} XIT update to DataChannel: {error}")
+                }
+            }
+        }
+        TransceiverSubsystem::Rig => warn!("XIT update without VFO ignored"),
+    }
+}
+
+async fn evt_level_updated(level: TransceiverLevel, value: f32, data_channel: Arc<RTCDataChannel>) {
+    let Some(trx_level) = transceiver_level_to_trx_level(level) else {
+        return;
+    };
+    let message = AgentControlMessage {
+        request_id: 0,
+        message: Some(Transceiver(
+            qsp_proto_files::qsp::message::v1::TransceiverMessage {
+                transceiver_message: Some(TransceiverPayload::TrxLevelMessage(TrxLevelMessage {
+                    level: trx_level as i32,
+                    value,
+                })),
+            },
+        )),
+    };
+
+    let bytes = Bytes::from(message.encode_to_vec());
+    match data_channel.send(&bytes).await {
+        Ok(_) => debug!("Sent {level:?} level update to DataChannel: {value}"),
+        Err(error) => error!("Failed to send {level:?} level update to DataChannel: {error}"),
+    }
+}
+
+async fn evt_function_updated(
+    function: TransceiverFunction,
+    enabled: bool,
+    data_channel: Arc<RTCDataChannel>,
+) {
+    let message = AgentControlMessage {
+        request_id: 0,
+        message: Some(Transceiver(
+            qsp_proto_files::qsp::message::v1::TransceiverMessage {
+                transceiver_message: Some(TransceiverPayload::TrxFunctionMessage(
+                    TrxFunctionMessage {
+                        function: transceiver_function_to_trx_function(function) as i32,
+                        enabled,
+                    },
+                )),
+            },
+        )),
+    };
+
+    let bytes = Bytes::from(message.encode_to_vec());
+    match data_channel.send(&bytes).await {
+        Ok(_) => debug!("Sent {function:?} function update to DataChannel: {enabled}"),
+        Err(error) => error!("Failed to send {function:?} function update to DataChannel: {error}"),
+    }
+}
+
+async fn evt_antennas_updated(
+    antennas: TransceiverAntennas,
+    transceiver_subsystem: TransceiverSubsystem,
+    data_channel: Arc<RTCDataChannel>,
+) {
+    match transceiver_subsystem {
+        TransceiverSubsystem::Vfo { id } => {
+            let message = AgentControlMessage {
+                request_id: 0,
+                message: Some(Transceiver(
+                    qsp_proto_files::qsp::message::v1::TransceiverMessage {
+                        transceiver_message: Some(TransceiverPayload::TrxAntennaMessage(
+                            TrxAntennaMessage {
+                                vfo_id: id,
+                                antenna: trx_antenna_from_hamlib_antenna(antennas.current) as i32,
+                                tx_antenna: trx_antenna_from_hamlib_antenna(antennas.tx) as i32,
+                                rx_antenna: trx_antenna_from_hamlib_antenna(antennas.rx) as i32,
+                                option: antennas.option,
+                            },
+                        )),
+                    },
+                )),
+            };
+
+            let bytes = Bytes::from(message.encode_to_vec());
+            match data_channel.send(&bytes).await {
+                Ok(_) => debug!("Sent VFO {id} antennas update to DataChannel: {antennas:?}"),
+                Err(error) => {
+                    error!("Failed to send VFO {id} antennas update to DataChannel: {error}")
+                }
+            }
+        }
+        TransceiverSubsystem::Rig => warn!("Antennas update without VFO ignored"),
+    }
+}
+
+async fn evt_meters_updated(
+    readings: Vec<TransceiverMeterReading>,
+    data_channel: Arc<RTCDataChannel>,
+) {
+    let message = AgentControlMessage {
+        request_id: 0,
+        message: Some(Transceiver(
+            qsp_proto_files::qsp::message::v1::TransceiverMessage {
+                transceiver_message: Some(TransceiverPayload::TrxMeterMessage(TrxMeterMessage {
+                    readings: readings
+                        .into_iter()
+                        .filter_map(|reading| {
+                            transceiver_level_to_trx_meter(reading.level).map(|meter| {
+                                TrxMeterReading {
+                                    meter: meter as i32,
+                                    value: reading.value,
+                                }
+                            })
+                        })
+                        .collect(),
+                })),
+            },
+        )),
+    };
+
+    let bytes = Bytes::from(message.encode_to_vec());
+    match data_channel.send(&bytes).await {
+        Ok(_) => trace!("Sent meter readings to DataChannel"),
+        Err(error) => error!("Failed to send meter readings to DataChannel: {error}"),
+    }
+}
+
+async fn evt_ptt_updated(
+    ptt: TransceiverPtt,
+    transceiver_subsystem: TransceiverSubsystem,
+    data_channel: Arc<RTCDataChannel>,
+) {
+    match transceiver_subsystem {
+        TransceiverSubsystem::Vfo { id } => {
+            let message = AgentControlMessage {
+                request_id: 0,
+                message: Some(Transceiver(
+                    qsp_proto_files::qsp::message::v1::TransceiverMessage {
+                        transceiver_message: Some(TransceiverPayload::TrxPttMessage(
+                            TrxPttMessage {
+                                vfo_id: id,
+                                ptt: transceiver_ptt_to_trx_ptt(ptt) as i32,
+                            },
+                        )),
+                    },
+                )),
+            };
+
+            let bytes = Bytes::from(message.encode_to_vec());
+            match data_channel.send(&bytes).await {
+                Ok(_) => debug!("Sent VFO {id} PTT update to DataChannel: {ptt:?}"),
+                Err(error) => {
+                    error!("Failed to send VFO {id} PTT update to DataChannel: {error}")
+                }
+            }
+        }
+        TransceiverSubsystem::Rig => warn!("PTT update without VFO ignored"),
+    }
+}
+
+async fn evt_connection_updated(
+    status: TransceiverConnectionStatus,
+    data_channel: Arc<RTCDataChannel>,
+) {
+    let message = AgentControlMessage {
+        request_id: 0,
+        message: Some(Transceiver(
+            qsp_proto_files::qsp::message::v1::TransceiverMessage {
+                transceiver_message: Some(TransceiverPayload::TrxConnectionMessage(
+                    TrxConnectionMessage {
+                        status: transceiver_connection_status_to_trx_connection_status(status)
+                            as i32,
+                    },
+                )),
+            },
+        )),
+    };
+
+    let bytes = Bytes::from(message.encode_to_vec());
+    match data_channel.send(&bytes).await {
+        Ok(_) => debug!("Sent transceiver connection status to DataChannel: {status:?}"),
+        Err(error) => {
+            error!("Failed to send transceiver connection status to DataChannel: {error}")
+        }
+    }
+}
+
+async fn evt_control_status_updated(
+    status: OperatorControlStatus,
+    session_uuid: &str,
+    data_channel: Arc<RTCDataChannel>,
+) {
+    let message = AgentControlMessage {
+        request_id: 0,
+        message: Some(Agent(qsp_proto_files::qsp::message::v1::AgentMessage {
+            agent_message: Some(AgentPayload::ControlStatus(ControlStatusMessage {
+                session_uuid: session_uuid.to_string(),
+                operator_session_uuid: status
+                    .operator_session_uuid
+                    .map(|uuid| uuid.to_string())
+                    .unwrap_or_default(),
+                pending_session_uuids: status
+                    .pending_requests
+                    .iter()
+                    .map(|uuid| uuid.to_string())
+                    .collect(),
+            })),
+        })),
+    };
+
+    let bytes = Bytes::from(message.encode_to_vec());
+    match data_channel.send(&bytes).await {
+        Ok(_) => debug!("Sent control status to DataChannel"),
+        Err(error) => error!("Failed to send control status to DataChannel: {error}"),
+    }
+}
+
+async fn evt_audio_profile_updated(
+    profile: AudioEncoderProfile,
+    data_channel: Arc<RTCDataChannel>,
+) {
+    let message = AgentControlMessage {
+        request_id: 0,
+        message: Some(Agent(qsp_proto_files::qsp::message::v1::AgentMessage {
+            agent_message: Some(AgentPayload::AudioProfile(
+                audio_profile_message_from_audio_encoder_profile(profile),
+            )),
+        })),
+    };
+
+    let bytes = Bytes::from(message.encode_to_vec());
+    match data_channel.send(&bytes).await {
+        Ok(_) => debug!("Sent audio profile to DataChannel: {profile:?}"),
+        Err(error) => error!("Failed to send audio profile to DataChannel: {error}"),
+    }
+}
+
+async fn evt_audio_status_updated(status: AudioStatusMessage, data_channel: Arc<RTCDataChannel>) {
+    let message = AgentControlMessage {
+        request_id: 0,
+        message: Some(Agent(qsp_proto_files::qsp::message::v1::AgentMessage {
+            agent_message: Some(AgentPayload::AudioStatus(status)),
+        })),
+    };
+
+    let bytes = Bytes::from(message.encode_to_vec());
+    match data_channel.send(&bytes).await {
+        Ok(_) => debug!("Sent audio status to DataChannel"),
+        Err(error) => error!("Failed to send audio status to DataChannel: {error}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        audio_encoder_profile_from_audio_profile_message, audio_status_message_from_audio_event,
+    };
+    use crate::configuration::{AudioEncoderApplication, AudioEncoderProfile};
+    use crate::hardware::audio_io::{AudioDirection, AudioError, AudioEvent};
+    use qsp_proto_files::qsp::message::v1::{
+        AudioApplication, AudioDeviceStatus, AudioDirection as ProtoAudioDirection,
+        AudioProfileMessage,
+    };
+    use std::time::Duration;
+
+    fn current_profile() -> AudioEncoderProfile {
+        AudioEncoderProfile {
+            application: AudioEncoderApplication::Voip,
+            bitrate: Some(24000),
+            frame_duration_ms: 40,
+            inband_fec: true,
+            dtx: true,
+            packet_loss_percent: 10,
+        }
+    }
+
+    #[test]
+    fn application_only_update_keeps_the_other_settings() {
+        let message = AudioProfileMessage {
+            application: AudioApplication::Audio as i32,
+            ..Default::default()
+        };
+
+        let profile = audio_encoder_profile_from_audio_profile_message(&message, current_profile());
+
+        assert_eq!(
+            profile,
+            AudioEncoderProfile {
+                application: AudioEncoderApplication::Audio,
+                ..current_profile()
+            }
+        );
+    }
+
+    #[test]
+    fn zero_bitrate_lets_the_encoder_choose() {
+        let message = AudioProfileMessage {
+            bitrate: Some(0),
+            dtx: Some(false),
+            ..Default::default()
+        };
+
+        let profile = audio_encoder_profile_from_audio_profile_message(&message, current_profile());
+
+        assert_eq!(profile.bitrate, None);
+        assert!(!profile.dtx);
+        assert!(profile.inband_fec);
+    }
+
+    #[test]
+    fn failed_audio_device_is_reported_with_the_retry_delay() {
+        let status = audio_status_message_from_audio_event(AudioEvent::Failed {
+            direction: AudioDirection::Output,
+            error: AudioError::DeviceNotFound("USB Audio CODEC".to_string()),
+            retry_in: Duration::from_secs(5),
+        })
+        .unwrap();
+
+        assert_eq!(status.direction, ProtoAudioDirection::Output as i32);
+        assert_eq!(status.status, AudioDeviceStatus::Failed as i32);
+        assert_eq!(status.error, "audio device 'USB Audio CODEC' not found");
+        assert_eq!(status.retry_in_seconds, 5);
+    }
+
+    #[test]
+    fn dropped_audio_frames_are_not_reported() {
+        let event = AudioEvent::FrameDropped {
+            direction: AudioDirection::Input,
+            error: AudioError::EncoderFailed("buffer too small".to_string()),
+        };
+
+        assert_eq!(audio_status_message_from_audio_event(event), None);
     }
 }
@@ -14,6 +14,7 @@ along with this program. If not, see <https://www.gnu.org/licenses/>
  */
 
 pub mod command_session;
+pub(crate) mod operator_control;
 mod transceiver_mapping;
 pub(crate) mod webrtc_session;
 pub(crate) mod webrtc_session_manager;
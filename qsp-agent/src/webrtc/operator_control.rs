@@ -0,0 +1,302 @@
+/*
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License,
+or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program. If not, see <https://www.gnu.org/licenses/>
+ */
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::info;
+
+/// Operator token shared by all the WebRTC sessions.
+///
+/// One session at a time holds the control of the transceiver and the TX rights.
+/// Other sessions are listeners: they receive the state and the audio, but their
+/// control messages are rejected.
+pub struct OperatorControl {
+    state: Mutex<OperatorControlState>,
+    idle_timeout: Duration,
+    status_senders: Mutex<Vec<UnboundedSender<OperatorControlStatus>>>,
+}
+
+struct OperatorControlState {
+    operator: Option<Operator>,
+    pending_requests: Vec<Arc<String>>,
+}
+
+struct Operator {
+    session_uuid: Arc<String>,
+    last_activity: Instant,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OperatorControlStatus {
+    pub operator_session_uuid: Option<Arc<String>>,
+    pub pending_requests: Vec<Arc<String>>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ControlRequestResult {
+    Granted,
+    Pending,
+}
+
+#[derive(thiserror::Error, Debug, Eq, PartialEq)]
+pub enum OperatorControlError {
+    #[error("session is not the operator")]
+    NotOperator,
+    #[error("session did not request the control")]
+    NotRequested,
+}
+
+impl OperatorControl {
+    pub fn new(idle_timeout: Duration) -> Self {
+        Self {
+            state: Mutex::new(OperatorControlState {
+                operator: None,
+                pending_requests: vec![],
+            }),
+            idle_timeout,
+            status_senders: Mutex::new(vec![]),
+        }
+    }
+
+    /// Gives the control to the session if nobody holds it, otherwise queues the request
+    /// until the operator releases or hands over the control.
+    pub fn request_control(&self, session_uuid: &Arc<String>) -> ControlRequestResult {
+        let mut state = self.state.lock().unwrap();
+        match state.operator.as_mut() {
+            Some(operator) if operator.session_uuid == *session_uuid => {
+                operator.last_activity = Instant::now();
+                return ControlRequestResult::Granted;
+            }
+            Some(_) => {
+                if !state.pending_requests.contains(session_uuid) {
+                    state.pending_requests.push(session_uuid.clone());
+                    self.send_status(&state);
+                }
+                return ControlRequestResult::Pending;
+            }
+            None => {}
+        }
+
+        info!("Session {} takes the transceiver control", session_uuid);
+        state
+            .pending_requests
+            .retain(|pending| pending != session_uuid);
+        state.operator = Some(Operator {
+            session_uuid: session_uuid.clone(),
+            last_activity: Instant::now(),
+        });
+        self.send_status(&state);
+        ControlRequestResult::Granted
+    }
+
+    /// Releases the control, or drops the pending request, of the session. The control
+    /// goes to the first pending request if any.
+    pub fn release_control(&self, session_uuid: &str) {
+        let mut state = self.state.lock().unwrap();
+        if Self::is_operator_state(&state, session_uuid) {
+            info!("Session {} releases the transceiver control", session_uuid);
+            Self::give_control_to_next(&mut state);
+            self.send_status(&state);
+        } else if state
+            .pending_requests
+            .iter()
+            .any(|pending| pending.as_str() == session_uuid)
+        {
+            state
+                .pending_requests
+                .retain(|pending| pending.as_str() != session_uuid);
+            self.send_status(&state);
+        }
+    }
+
+    /// Hands over the control from the operator to a session waiting for it.
+    pub fn handover_control(
+        &self,
+        session_uuid: &str,
+        target_session_uuid: &str,
+    ) -> Result<(), OperatorControlError> {
+        let mut state = self.state.lock().unwrap();
+        if !Self::is_operator_state(&state, session_uuid) {
+            return Err(OperatorControlError::NotOperator);
+        }
+        let position = state
+            .pending_requests
+            .iter()
+            .position(|pending| pending.as_str() == target_session_uuid)
+            .ok_or(OperatorControlError::NotRequested)?;
+
+        let target_session_uuid = state.pending_requests.remove(position);
+        info!(
+            "Session {} hands over the transceiver control to session {}",
+            session_uuid, target_session_uuid
+        );
+        state.operator = Some(Operator {
+            session_uuid: target_session_uuid,
+            last_activity: Instant::now(),
+        });
+        self.send_status(&state);
+        Ok(())
+    }
+
+    pub fn is_operator(&self, session_uuid: &str) -> bool {
+        Self::is_operator_state(&self.state.lock().unwrap(), session_uuid)
+    }
+
+    /// Records an activity of the operator. Returns false if the session is not the operator.
+    pub fn touch(&self, session_uuid: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.operator.as_mut() {
+            Some(operator) if operator.session_uuid.as_str() == session_uuid => {
+                operator.last_activity = Instant::now();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn expire_idle_operator(&self) {
+        let mut state = self.state.lock().unwrap();
+        let idle_session_uuid = state
+            .operator
+            .as_ref()
+            .filter(|operator| operator.last_activity.elapsed() >= self.idle_timeout)
+            .map(|operator| operator.session_uuid.clone());
+
+        if let Some(session_uuid) = idle_session_uuid {
+            info!(
+                "Session {} idle for {} seconds, release the transceiver control",
+                session_uuid,
+                self.idle_timeout.as_secs()
+            );
+            Self::give_control_to_next(&mut state);
+            self.send_status(&state);
+        }
+    }
+
+    pub fn status(&self) -> OperatorControlStatus {
+        Self::status_from_state(&self.state.lock().unwrap())
+    }
+
+    pub fn add_status_receiver(&self) -> UnboundedReceiver<OperatorControlStatus> {
+        let (sender, receiver) = unbounded_channel();
+        self.status_senders.lock().unwrap().push(sender);
+        receiver
+    }
+
+    fn give_control_to_next(state: &mut OperatorControlState) {
+        state.operator = if state.pending_requests.is_empty() {
+            None
+        } else {
+            Some(Operator {
+                session_uuid: state.pending_requests.remove(0),
+                last_activity: Instant::now(),
+            })
+        };
+    }
+
+    fn is_operator_state(state: &OperatorControlState, session_uuid: &str) -> bool {
+        state
+            .operator
+            .as_ref()
+            .is_some_and(|operator| operator.session_uuid.as_str() == session_uuid)
+    }
+
+    fn status_from_state(state: &OperatorControlState) -> OperatorControlStatus {
+        OperatorControlStatus {
+            operator_session_uuid: state
+                .operator
+                .as_ref()
+                .map(|operator| operator.session_uuid.clone()),
+            pending_requests: state.pending_requests.clone(),
+        }
+    }
+
+    fn send_status(&self, state: &OperatorControlState) {
+        let status = Self::status_from_state(state);
+        self.status_senders
+            .lock()
+            .unwrap()
+            .retain(|sender| sender.send(status.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ControlRequestResult, OperatorControl, OperatorControlError};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn session(uuid: &str) -> Arc<String> {
+        Arc::new(uuid.to_string())
+    }
+
+    #[test]
+    fn first_request_takes_control_and_next_ones_are_pending() {
+        let control = OperatorControl::new(Duration::from_secs(60));
+        let (a, b) = (session("a"), session("b"));
+
+        assert_eq!(control.request_control(&a), ControlRequestResult::Granted);
+        assert_eq!(control.request_control(&b), ControlRequestResult::Pending);
+        assert!(control.touch("a"));
+        assert!(!control.touch("b"));
+    }
+
+    #[test]
+    fn release_gives_control_to_first_pending_request() {
+        let control = OperatorControl::new(Duration::from_secs(60));
+        let (a, b, c) = (session("a"), session("b"), session("c"));
+        control.request_control(&a);
+        control.request_control(&b);
+        control.request_control(&c);
+
+        control.release_control("a");
+
+        let status = control.status();
+        assert_eq!(status.operator_session_uuid, Some(b));
+        assert_eq!(status.pending_requests, vec![c]);
+    }
+
+    #[test]
+    fn handover_requires_operator_and_pending_target() {
+        let control = OperatorControl::new(Duration::from_secs(60));
+        let (a, b, c) = (session("a"), session("b"), session("c"));
+        control.request_control(&a);
+        control.request_control(&b);
+
+        assert_eq!(
+            control.handover_control("b", "a"),
+            Err(OperatorControlError::NotOperator)
+        );
+        assert_eq!(
+            control.handover_control("a", &c),
+            Err(OperatorControlError::NotRequested)
+        );
+        assert_eq!(control.handover_control("a", &b), Ok(()));
+        assert!(control.is_operator("b"));
+    }
+
+    #[test]
+    fn idle_operator_loses_control() {
+        let control = OperatorControl::new(Duration::ZERO);
+        let a = session("a");
+        control.request_control(&a);
+
+        control.expire_idle_operator();
+
+        assert_eq!(control.status().operator_session_uuid, None);
+    }
+}
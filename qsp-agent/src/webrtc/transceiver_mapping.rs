@@ -12,8 +12,13 @@ GNU General Public License for more details.
 You should have received a copy of the GNU General Public License
 along with this program. If not, see <https://www.gnu.org/licenses/>
  */
-use crate::hardware::transceiver::transceiver_state::{TransceiverBand, TransceiverMode};
-use qsp_proto_files::qsp::message::v1::{Band, TrxVfoMode};
+use crate::hardware::transceiver::transceiver_state::{
+    TransceiverBand, TransceiverConnectionStatus, TransceiverFunction, TransceiverLevel,
+    TransceiverMode, TransceiverPassband, TransceiverPtt,
+};
+use qsp_proto_files::qsp::message::v1::{
+    Band, TrxConnectionStatus, TrxFunction, TrxLevel, TrxMeter, TrxPassband, TrxPtt, TrxVfoMode,
+};
 
 pub(super) fn trx_vfo_mode_to_transceiver_mode(mode: TrxVfoMode) -> Option<TransceiverMode> {
     match mode {
@@ -123,3 +128,118 @@ pub(super) fn band_to_transceiver_band(band: Band) -> Option<TransceiverBand> {
         Band::Band12mm => Some(TransceiverBand::Band12mm),
     }
 }
+
+/// An explicit width takes precedence over the passband choice.
+pub(super) fn trx_passband_to_transceiver_passband(
+    passband: TrxPassband,
+    passband_hz: u32,
+) -> TransceiverPassband {
+    if passband_hz != 0 {
+        return TransceiverPassband::Hz(passband_hz);
+    }
+    match passband {
+        TrxPassband::Unspecified | TrxPassband::Normal => TransceiverPassband::Normal,
+        TrxPassband::Narrow => TransceiverPassband::Narrow,
+        TrxPassband::Wide => TransceiverPassband::Wide,
+    }
+}
+
+pub(super) fn trx_ptt_to_transceiver_ptt(ptt: TrxPtt) -> Option<TransceiverPtt> {
+    match ptt {
+        TrxPtt::Unspecified => None,
+        TrxPtt::Off => Some(TransceiverPtt::Off),
+        TrxPtt::On => Some(TransceiverPtt::On),
+        TrxPtt::OnMic => Some(TransceiverPtt::OnMic),
+        TrxPtt::OnData => Some(TransceiverPtt::OnData),
+    }
+}
+
+pub(super) fn transceiver_ptt_to_trx_ptt(ptt: TransceiverPtt) -> TrxPtt {
+    match ptt {
+        TransceiverPtt::Off => TrxPtt::Off,
+        TransceiverPtt::On => TrxPtt::On,
+        TransceiverPtt::OnMic => TrxPtt::OnMic,
+        TransceiverPtt::OnData => TrxPtt::OnData,
+    }
+}
+
+pub(super) fn transceiver_connection_status_to_trx_connection_status(
+    status: TransceiverConnectionStatus,
+) -> TrxConnectionStatus {
+    match status {
+        TransceiverConnectionStatus::Connected => TrxConnectionStatus::Connected,
+        TransceiverConnectionStatus::Degraded => TrxConnectionStatus::Degraded,
+        TransceiverConnectionStatus::Disconnected => TrxConnectionStatus::Disconnected,
+        TransceiverConnectionStatus::Reconnecting => TrxConnectionStatus::Reconnecting,
+    }
+}
+
+pub(super) fn transceiver_level_to_trx_meter(level: TransceiverLevel) -> Option<TrxMeter> {
+    match level {
+        TransceiverLevel::Strength => Some(TrxMeter::Strength),
+        TransceiverLevel::RfPowerMeter => Some(TrxMeter::RfPower),
+        TransceiverLevel::Swr => Some(TrxMeter::Swr),
+        TransceiverLevel::Alc => Some(TrxMeter::Alc),
+        TransceiverLevel::CompMeter => Some(TrxMeter::Compression),
+        TransceiverLevel::VdMeter => Some(TrxMeter::SupplyVoltage),
+        TransceiverLevel::IdMeter => Some(TrxMeter::DrainCurrent),
+        _ => None,
+    }
+}
+
+pub(super) fn trx_level_to_transceiver_level(level: TrxLevel) -> Option<TransceiverLevel> {
+    match level {
+        TrxLevel::Unspecified => None,
+        TrxLevel::RfPower => Some(TransceiverLevel::RfPower),
+        TrxLevel::AfGain => Some(TransceiverLevel::AfGain),
+        TrxLevel::RfGain => Some(TransceiverLevel::RfGain),
+        TrxLevel::Squelch => Some(TransceiverLevel::Squelch),
+        TrxLevel::MicGain => Some(TransceiverLevel::MicGain),
+        TrxLevel::KeyerSpeed => Some(TransceiverLevel::KeyerSpeed),
+        TrxLevel::NoiseReduction => Some(TransceiverLevel::NoiseReduction),
+        TrxLevel::NoiseBlanker => Some(TransceiverLevel::NoiseBlanker),
+    }
+}
+
+pub(super) fn transceiver_level_to_trx_level(level: TransceiverLevel) -> Option<TrxLevel> {
+    match level {
+        TransceiverLevel::RfPower => Some(TrxLevel::RfPower),
+        TransceiverLevel::AfGain => Some(TrxLevel::AfGain),
+        TransceiverLevel::RfGain => Some(TrxLevel::RfGain),
+        TransceiverLevel::Squelch => Some(TrxLevel::Squelch),
+        TransceiverLevel::MicGain => Some(TrxLevel::MicGain),
+        TransceiverLevel::KeyerSpeed => Some(TrxLevel::KeyerSpeed),
+        TransceiverLevel::NoiseReduction => Some(TrxLevel::NoiseReduction),
+        TransceiverLevel::NoiseBlanker => Some(TrxLevel::NoiseBlanker),
+        _ => None,
+    }
+}
+
+pub(super) fn trx_function_to_transceiver_function(
+    function: TrxFunction,
+) -> Option<TransceiverFunction> {
+    match function {
+        TrxFunction::Unspecified => None,
+        TrxFunction::NoiseBlanker => Some(TransceiverFunction::NoiseBlanker),
+        TrxFunction::NoiseReduction => Some(TransceiverFunction::NoiseReduction),
+        TrxFunction::AutoNotch => Some(TransceiverFunction::AutoNotch),
+        TrxFunction::Vox => Some(TransceiverFunction::Vox),
+        TrxFunction::Compressor => Some(TransceiverFunction::Compressor),
+        TrxFunction::Tone => Some(TransceiverFunction::Tone),
+        TrxFunction::Lock => Some(TransceiverFunction::Lock),
+        TrxFunction::Tuner => Some(TransceiverFunction::Tuner),
+    }
+}
+
+pub(super) fn transceiver_function_to_trx_function(function: TransceiverFunction) -> TrxFunction {
+    match function {
+        TransceiverFunction::NoiseBlanker => TrxFunction::NoiseBlanker,
+        TransceiverFunction::NoiseReduction => TrxFunction::NoiseReduction,
+        TransceiverFunction::AutoNotch => TrxFunction::AutoNotch,
+        TransceiverFunction::Vox => TrxFunction::Vox,
+        TransceiverFunction::Compressor => TrxFunction::Compressor,
+        TransceiverFunction::Tone => TrxFunction::Tone,
+        TransceiverFunction::Lock => TrxFunction::Lock,
+        TransceiverFunction::Tuner => TrxFunction::Tuner,
+    }
+}
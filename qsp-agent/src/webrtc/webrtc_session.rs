@@ -17,10 +17,13 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
 use anyhow::Result;
-use flume::Receiver;
+use flume::Sender;
 use prost::Message;
 use qsp_proto_files::qsp::message::v1::AgentControlMessage;
+use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::Notify;
+use tokio::task::AbortHandle;
+use tokio::time::Duration;
 use tracing::{debug, error, info};
 use uuid::Uuid;
 use webrtc::api::interceptor_registry::register_default_interceptors;
@@ -28,8 +31,8 @@ use webrtc::api::media_engine::{MediaEngine, MIME_TYPE_OPUS};
 use webrtc::api::APIBuilder;
 use webrtc::data_channel::data_channel_message::DataChannelMessage;
 use webrtc::data_channel::RTCDataChannel;
+use webrtc::ice_transport::ice_candidate::{RTCIceCandidate, RTCIceCandidateInit};
 use webrtc::ice_transport::ice_connection_state::RTCIceConnectionState;
-use webrtc::ice_transport::ice_server::RTCIceServer;
 use webrtc::interceptor::registry::Registry;
 use webrtc::media::Sample;
 use webrtc::peer_connection::configuration::RTCConfiguration;
@@ -37,28 +40,49 @@ use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
 use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
 use webrtc::peer_connection::RTCPeerConnection;
 use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+use webrtc::rtp_transceiver::rtp_receiver::RTCRtpReceiver;
+use webrtc::rtp_transceiver::RTCRtpTransceiver;
 use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
 use webrtc::track::track_local::TrackLocal;
+use webrtc::track::track_remote::TrackRemote;
 
+use crate::audio::broadcast::FrameSubscriber;
 use crate::audio::AudioEncodedFrame;
+use crate::hardware::audio_io::AudioSessionManager;
 use crate::hardware::transceiver::transceiver_manager::TransceiverManager;
 use crate::webrtc::command_session::CommandSession;
+use crate::webrtc::operator_control::OperatorControl;
+
+/// ICE candidate gathered by the agent, to be relayed to the client when trickle ICE is
+/// used. A `None` candidate means the end of the gathering.
+pub struct LocalIceCandidate {
+    pub session_uuid: Arc<String>,
+    pub candidate: Option<RTCIceCandidateInit>,
+}
 
 pub struct WebrtcSession {
     pub agent_rtc_uuid: Arc<String>,
     peer_rtc_connection: Option<Arc<RTCPeerConnection>>,
-    encoded_receiver: Receiver<AudioEncodedFrame>,
     command_session: Arc<Mutex<Option<CommandSession>>>,
+    connected: Arc<AtomicBool>,
+    tasks: Vec<AbortHandle>,
     pub agent_sdp: Arc<String>,
 }
 
 impl WebrtcSession {
     pub(super) async fn create_session(
         client_sdp: String,
-        encoded_receiver: Receiver<AudioEncodedFrame>,
+        rtc_config: RTCConfiguration,
+        audio_subscriber: FrameSubscriber<AudioEncodedFrame>,
+        playback_sender: Sender<AudioEncodedFrame>,
+        audio_session_manager: Arc<Mutex<AudioSessionManager>>,
         transceiver_manager: Arc<TransceiverManager>,
+        operator_control: Arc<OperatorControl>,
+        closed_session_sender: UnboundedSender<Arc<String>>,
+        candidate_sender: Option<UnboundedSender<LocalIceCandidate>>,
     ) -> Result<WebrtcSession> {
         debug!("Starting webRTC session");
+        let agent_rtc_uuid = Arc::new(Uuid::new_v4().to_string());
         // Create a MediaEngine object to configure the supported codec
         let mut m = MediaEngine::default();
 
@@ -79,15 +103,6 @@ impl WebrtcSession {
             .with_interceptor_registry(registry)
             .build();
 
-        // Prepare the configuration
-        let rtc_config = RTCConfiguration {
-            ice_servers: vec![RTCIceServer {
-                urls: vec!["stun:stun.l.google.com:19302".to_owned()],
-                ..Default::default()
-            }],
-            ..Default::default()
-        };
-
         // Create a new RTCPeerConnection
         let peer_connection = Arc::new(api.new_peer_connection(rtc_config).await?);
         debug!("RTC peer connection created");
@@ -115,8 +130,9 @@ impl WebrtcSession {
         // Read incoming RTCP packets
         // Before these packets are returned they are processed by interceptors. For things
         // like NACK this needs to be called.
+        let mut tasks = vec![];
         let connected_rtcp_reader = connected.clone();
-        let _ = tokio::task::Builder::new()
+        let rtcp_reader = tokio::task::Builder::new()
             .name("RTCP Reader")
             .spawn(async move {
                 let mut rtcp_buf = vec![0u8; 1500];
@@ -131,12 +147,12 @@ impl WebrtcSession {
                     }
                 }
                 debug!("End thread: RTCP reader");
-            });
+            })?;
+        tasks.push(rtcp_reader.abort_handle());
 
         // SENDER
         let connected_sender = connected.clone();
-        let audio_receiver = encoded_receiver.clone();
-        let _ = tokio::task::Builder::new()
+        let audio_sender = tokio::task::Builder::new()
             .name("Audio sender")
             .spawn(async move {
                 // Wait for connection established
@@ -144,17 +160,19 @@ impl WebrtcSession {
 
                 debug!("Start thread : Send the audio from the encoder");
                 while connected_sender.load(Ordering::Relaxed) {
-                    match audio_receiver.recv_async().await {
-                        Ok(frame) => {
+                    match audio_subscriber.recv().await {
+                        Some(frame) => {
                             audio_track
                                 .write_sample(&Sample {
                                     data: frame.bytes,
                                     duration: frame.duration,
+                                    // Keeps the RTP timestamps in step over the DTX gaps
+                                    prev_dropped_packets: frame.skipped_frames,
                                     ..Default::default()
                                 })
                                 .await?;
                         }
-                        Err(_) => {
+                        None => {
                             break;
                         }
                     }
@@ -162,7 +180,8 @@ impl WebrtcSession {
                 debug!("End send audio thread");
 
                 Result::<()>::Ok(())
-            });
+            })?;
+        tasks.push(audio_sender.abort_handle());
 
         debug!("Audio track created");
 
@@ -181,28 +200,52 @@ impl WebrtcSession {
         // Set the handler for Peer connection state
         // This will notify you when the peer has connected/disconnected
         let connected_store = connected.clone();
+        let ptt_transceiver_manager = transceiver_manager.clone();
+        let ptt_session_uuid = agent_rtc_uuid.clone();
+        let failed_operator_control = operator_control.clone();
         peer_connection.on_peer_connection_state_change(Box::new(
             move |s: RTCPeerConnectionState| {
                 debug!("Peer Connection State has changed: {}", s);
+                // The peer may come back from Disconnected, but we can't keep transmitting
+                // without a remote operator. Unkey as soon as the link is lost.
+                if matches!(
+                    s,
+                    RTCPeerConnectionState::Disconnected
+                        | RTCPeerConnectionState::Failed
+                        | RTCPeerConnectionState::Closed
+                ) {
+                    ptt_transceiver_manager.release_ptt(&ptt_session_uuid);
+                }
                 // Remove session
                 // Wait until PeerConnection has had no network activity for 30 seconds or another failure. It may be reconnected using an ICE Restart.
                 // Use webrtc.PeerConnectionStateDisconnected if you are interested in detecting faster timeout.
                 // Note that the PeerConnection may come back from PeerConnectionStateDisconnected.
-                info!("Peer Connection has gone to failed exiting");
-                if s == RTCPeerConnectionState::Failed {
-                    // let _ = done_tx.try_send(());
+                if s == RTCPeerConnectionState::Failed || s == RTCPeerConnectionState::Closed {
+                    info!("Peer Connection has gone to {}, remove the session", s);
                     connected_store.store(false, Ordering::Relaxed);
+                    failed_operator_control.release_control(&ptt_session_uuid);
+                    let _ = closed_session_sender.send(ptt_session_uuid.clone());
                 }
 
                 Box::pin(async {})
             },
         ));
 
+        Self::register_track_handler(
+            &peer_connection,
+            playback_sender,
+            agent_rtc_uuid.clone(),
+            operator_control.clone(),
+        );
+
         let command_session = Arc::new(Mutex::new(None));
         Self::register_data_channel_handler(
             &peer_connection,
             Arc::clone(&command_session),
+            agent_rtc_uuid.clone(),
+            audio_session_manager,
             transceiver_manager,
+            operator_control,
         );
 
         // Wait for the offer to be pasted
@@ -216,19 +259,28 @@ impl WebrtcSession {
         let answer = peer_connection.create_answer(None).await?;
         debug!("RTC answer created");
 
-        // Create channel that is blocked until ICE Gathering is complete
-        let mut gather_complete = peer_connection.gathering_complete_promise().await;
+        if let Some(candidate_sender) = candidate_sender {
+            // Trickle ICE: answer right away, the candidates follow through the signaling server
+            Self::register_ice_candidate_handler(
+                &peer_connection,
+                agent_rtc_uuid.clone(),
+                candidate_sender,
+            );
+            peer_connection.set_local_description(answer).await?;
+            debug!("Local description set");
+        } else {
+            // Create channel that is blocked until ICE Gathering is complete
+            let mut gather_complete = peer_connection.gathering_complete_promise().await;
 
-        // Sets the LocalDescription, and starts our UDP listeners
-        peer_connection.set_local_description(answer).await?;
-        debug!("Local description set");
+            // Sets the LocalDescription, and starts our UDP listeners
+            peer_connection.set_local_description(answer).await?;
+            debug!("Local description set");
 
-        // Block until ICE Gathering is complete, disabling trickle ICE
-        // we do this because we only can exchange one signaling message
-        // in a production application you should exchange ICE Candidates via OnICECandidate
-        //TODO Check this comment about OnIceCandidate
-        let _ = gather_complete.recv().await;
-        debug!("ICE gathering complete");
+            // Block until ICE Gathering is complete, the client doesn't support trickle ICE
+            // and gets all the candidates in the answer
+            let _ = gather_complete.recv().await;
+            debug!("ICE gathering complete");
+        }
 
         let mut agent_sdp: Option<String> = Option::None;
         // Get the answer to return to the server, then to the browser
@@ -239,32 +291,148 @@ impl WebrtcSession {
         }
 
         let session = WebrtcSession {
-            agent_rtc_uuid: Arc::new(Uuid::new_v4().to_string()),
+            agent_rtc_uuid,
             peer_rtc_connection: Some(peer_connection),
-            encoded_receiver,
             command_session,
+            connected,
+            tasks,
             agent_sdp: Arc::new(agent_sdp.unwrap()),
         };
         Ok(session)
     }
 
+    pub(super) fn peer_connection(&self) -> Option<Arc<RTCPeerConnection>> {
+        self.peer_rtc_connection.clone()
+    }
+
+    /// The peer connection failed or was closed.
+    pub(super) fn is_closed(&self) -> bool {
+        !self.connected.load(Ordering::Relaxed)
+    }
+
+    /// Stops the session tasks and closes the peer connection.
+    pub(super) async fn close(&self) {
+        debug!("Close webRTC session {}", self.agent_rtc_uuid);
+        self.connected.store(false, Ordering::Relaxed);
+        for task in &self.tasks {
+            task.abort();
+        }
+        // Dropping the command session stops its transceiver and control event loops
+        self.command_session.lock().unwrap().take();
+
+        if let Some(peer_connection) = &self.peer_rtc_connection {
+            if let Err(error) = peer_connection.close().await {
+                error!(
+                    "Failed to close peer connection of session {}: {}",
+                    self.agent_rtc_uuid, error
+                );
+            }
+        }
+    }
+
+    fn register_ice_candidate_handler(
+        peer_connection: &Arc<RTCPeerConnection>,
+        session_uuid: Arc<String>,
+        candidate_sender: UnboundedSender<LocalIceCandidate>,
+    ) {
+        peer_connection.on_ice_candidate(Box::new(move |candidate: Option<RTCIceCandidate>| {
+            let candidate = match candidate.map(|c| c.to_json()).transpose() {
+                Ok(candidate) => candidate,
+                Err(error) => {
+                    error!("Failed to serialize local ICE candidate: {}", error);
+                    return Box::pin(async {});
+                }
+            };
+            debug!(
+                "Local ICE candidate gathered for session {}: {:?}",
+                session_uuid, candidate
+            );
+            let _ = candidate_sender.send(LocalIceCandidate {
+                session_uuid: session_uuid.clone(),
+                candidate,
+            });
+            Box::pin(async {})
+        }));
+    }
+
+    fn register_track_handler(
+        peer_connection: &Arc<RTCPeerConnection>,
+        playback_sender: Sender<AudioEncodedFrame>,
+        session_uuid: Arc<String>,
+        operator_control: Arc<OperatorControl>,
+    ) {
+        peer_connection.on_track(Box::new(
+            move |track: Arc<TrackRemote>,
+                  _receiver: Arc<RTCRtpReceiver>,
+                  _transceiver: Arc<RTCRtpTransceiver>| {
+                let mime_type = track.codec().capability.mime_type;
+                if !mime_type.eq_ignore_ascii_case(MIME_TYPE_OPUS) {
+                    info!("Ignore remote track with unsupported codec {}", mime_type);
+                    return Box::pin(async {});
+                }
+                debug!("Remote audio track received");
+                let playback_sender = playback_sender.clone();
+                let session_uuid = session_uuid.clone();
+                let operator_control = operator_control.clone();
+
+                Box::pin(async move {
+                    let _ = tokio::task::Builder::new()
+                        .name("Audio receiver")
+                        .spawn(async move {
+                            debug!("Start thread: Receive the audio from the client");
+                            while let Ok((packet, _)) = track.read_rtp().await {
+                                // Only the operator is allowed to transmit
+                                if packet.payload.is_empty()
+                                    || !operator_control.is_operator(&session_uuid)
+                                {
+                                    continue;
+                                }
+                                let duration = opus::packet::get_nb_samples(&packet.payload, 48000)
+                                    .map(|sample_count| {
+                                        Duration::from_millis(sample_count as u64 * 1000 / 48000)
+                                    })
+                                    .unwrap_or_default();
+                                let frame = AudioEncodedFrame {
+                                    bytes: packet.payload,
+                                    duration,
+                                    skipped_frames: 0,
+                                };
+                                if playback_sender.send_async(frame).await.is_err() {
+                                    break;
+                                }
+                            }
+                            debug!("End thread: Receive the audio from the client");
+                        });
+                })
+            },
+        ));
+    }
+
     fn register_data_channel_handler(
         peer_connection: &Arc<RTCPeerConnection>,
         command_session_store: Arc<Mutex<Option<CommandSession>>>,
+        session_uuid: Arc<String>,
+        audio_session_manager: Arc<Mutex<AudioSessionManager>>,
         transceiver_manager: Arc<TransceiverManager>,
+        operator_control: Arc<OperatorControl>,
     ) {
         peer_connection.on_data_channel(Box::new(move |data_channel: Arc<RTCDataChannel>| {
             let d_label = data_channel.label().to_owned();
             let d_id = data_channel.id();
             debug!("New DataChannel {d_label} {d_id}");
-            *command_session_store.lock().unwrap() = Some(CommandSession::new(data_channel.clone(), transceiver_manager.clone()));
+            *command_session_store.lock().unwrap() = Some(CommandSession::new(data_channel.clone(), session_uuid.clone(), audio_session_manager.clone(), transceiver_manager.clone(), operator_control.clone()));
             let command_session_for_messages = Arc::clone(&command_session_store);
+            let ptt_session_uuid = session_uuid.clone();
+            let ptt_transceiver_manager = transceiver_manager.clone();
+            let closed_operator_control = operator_control.clone();
 
             Box::pin(async move {
                 let d_label2 = d_label.clone();
                 let d_id2 = d_id;
                 data_channel.on_close(Box::new(move || {
                     debug!("Data channel closed");
+                    ptt_transceiver_manager.release_ptt(&ptt_session_uuid);
+                    closed_operator_control.release_control(&ptt_session_uuid);
                     Box::pin(async {})
                 }));
 
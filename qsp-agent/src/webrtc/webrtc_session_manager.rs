@@ -13,62 +13,202 @@ You should have received a copy of the GNU General Public License
 along with this program. If not, see <https://www.gnu.org/licenses/>
  */
 
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use anyhow::Result;
-use flume::Receiver;
-use tracing::{debug, info};
+use anyhow::{anyhow, Result};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tracing::{debug, info, warn};
+use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
+use webrtc::ice_transport::ice_server::RTCIceServer;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::policy::ice_transport_policy::RTCIceTransportPolicy;
 
-use crate::audio::AudioEncodedFrame;
+use crate::configuration::{Configuration, IceServer, IceTransportPolicy};
 use crate::hardware::audio_io::AudioSessionManager;
 use crate::hardware::transceiver::transceiver_manager::TransceiverManager;
-use crate::webrtc::webrtc_session::WebrtcSession;
+use crate::webrtc::operator_control::OperatorControl;
+use crate::webrtc::webrtc_session::{LocalIceCandidate, WebrtcSession};
+
+const OPERATOR_IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+type SessionList = Arc<Mutex<Vec<WebrtcSession>>>;
 
 pub struct WebrtcSessionManager {
-    sessions: Mutex<Vec<WebrtcSession>>,
-    encoded_receiver: Receiver<AudioEncodedFrame>,
-    _session_manager: Arc<Mutex<AudioSessionManager>>,
+    sessions: SessionList,
+    max_sessions: usize,
+    // Sessions being created, counted against the maximum
+    pending_sessions: AtomicUsize,
+    closed_session_sender: UnboundedSender<Arc<String>>,
+    audio_session_manager: Arc<Mutex<AudioSessionManager>>,
     transceiver_manager: Arc<TransceiverManager>,
+    operator_control: Arc<OperatorControl>,
+    ice_servers: Vec<RTCIceServer>,
+    ice_transport_policy: RTCIceTransportPolicy,
 }
 
 impl WebrtcSessionManager {
     pub fn new(
+        configuration: Configuration,
         session_manager: Arc<Mutex<AudioSessionManager>>,
         transceiver_manager: Arc<TransceiverManager>,
     ) -> Self {
+        let operator_control = Arc::new(OperatorControl::new(Duration::from_secs(
+            configuration.sessions.operator_idle_timeout_seconds,
+        )));
+
+        let idle_operator_control = operator_control.clone();
+        let _ = tokio::task::Builder::new()
+            .name("Operator idle check")
+            .spawn(async move {
+                let mut interval = tokio::time::interval(OPERATOR_IDLE_CHECK_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    idle_operator_control.expire_idle_operator();
+                }
+            });
+
+        // The output device is only needed while someone may transmit
+        let mut operator_status_receiver = operator_control.add_status_receiver();
+        let playback_session_manager = session_manager.clone();
+        let _ = tokio::task::Builder::new()
+            .name("Operator playback")
+            .spawn(async move {
+                while let Some(status) = operator_status_receiver.recv().await {
+                    playback_session_manager
+                        .lock()
+                        .unwrap()
+                        .set_operator_present(status.operator_session_uuid.is_some());
+                }
+            });
+
+        let sessions: SessionList = Arc::new(Mutex::new(Vec::new()));
+
+        let (closed_session_sender, mut closed_session_receiver) = unbounded_channel();
+        let closed_sessions = sessions.clone();
+        let _ = tokio::task::Builder::new()
+            .name("Closed session cleaner")
+            .spawn(async move {
+                while let Some(uuid) = closed_session_receiver.recv().await {
+                    if let Some(session) = Self::remove_session(&closed_sessions, &uuid) {
+                        debug!("Remove closed session {}", uuid);
+                        session.close().await;
+                    }
+                }
+            });
+
         Self {
-            sessions: Mutex::new(Vec::new()),
-            _session_manager: session_manager.clone(),
-            encoded_receiver: session_manager.lock().unwrap().get_audio_receiver(),
+            sessions,
+            max_sessions: configuration.sessions.max_sessions,
+            pending_sessions: AtomicUsize::new(0),
+            closed_session_sender,
+            audio_session_manager: session_manager.clone(),
             transceiver_manager,
+            operator_control,
+            ice_servers: configuration
+                .webrtc
+                .ice_servers
+                .into_iter()
+                .map(RTCIceServer::from)
+                .collect(),
+            ice_transport_policy: configuration.webrtc.ice_transport_policy.into(),
         }
     }
 
-    pub async fn add_session(&self, client_sdp: String) -> Result<(Box<String>, Arc<String>)> {
+    /// Creates a session answering the client SDP offer. The ICE servers given by the
+    /// signaling server replace the configured ones. With a candidate sender, the answer
+    /// is returned before the ICE gathering and the candidates are sent as they come.
+    pub async fn add_session(
+        &self,
+        client_sdp: String,
+        ice_servers: Option<Vec<RTCIceServer>>,
+        candidate_sender: Option<UnboundedSender<LocalIceCandidate>>,
+    ) -> Result<(Box<String>, Arc<String>)> {
+        // Reserve the slot before creating the session, concurrent client inits can't
+        // exceed the maximum
+        let reservation = {
+            let sessions = self.sessions.lock().unwrap();
+            let session_count = sessions.len() + self.pending_sessions.load(Ordering::Relaxed);
+            if session_count >= self.max_sessions {
+                warn!(
+                    "Reject new session: maximum of {} sessions reached",
+                    self.max_sessions
+                );
+                return Err(anyhow!(
+                    "maximum number of sessions reached ({})",
+                    self.max_sessions
+                ));
+            }
+            self.pending_sessions.fetch_add(1, Ordering::Relaxed);
+            SessionReservation {
+                pending_sessions: &self.pending_sessions,
+            }
+        };
+
+        let (audio_subscriber, playback_sender) = {
+            let mut audio_session_manager = self.audio_session_manager.lock().unwrap();
+            (
+                audio_session_manager.subscribe_audio(),
+                audio_session_manager.get_audio_playback_sender(),
+            )
+        };
+        let rtc_config = RTCConfiguration {
+            ice_servers: ice_servers.unwrap_or_else(|| self.ice_servers.clone()),
+            ice_transport_policy: self.ice_transport_policy,
+            ..Default::default()
+        };
         let session = WebrtcSession::create_session(
             client_sdp,
-            self.encoded_receiver.clone(),
+            rtc_config,
+            audio_subscriber,
+            playback_sender,
+            self.audio_session_manager.clone(),
             self.transceiver_manager.clone(),
+            self.operator_control.clone(),
+            self.closed_session_sender.clone(),
+            candidate_sender,
         )
-        .await
-        .expect("Start RTC session failed");
-        let mut sessions = self.sessions.lock().unwrap();
+        .await?;
         let uuid = session.agent_rtc_uuid.clone();
         let agent_sdp = Box::new(session.agent_sdp.as_ref().clone());
-
-        sessions.push(session);
-        Ok((agent_sdp, uuid))
+        {
+            let mut sessions = self.sessions.lock().unwrap();
+            drop(reservation);
+            if !session.is_closed() {
+                sessions.push(session);
+                return Ok((agent_sdp, uuid));
+            }
+        }
+        // The peer failed during the creation. Its close notification came before the
+        // session was in the list, nothing would remove it later.
+        session.close().await;
+        Err(anyhow!(
+            "peer connection closed during the session creation"
+        ))
     }
 
-    pub async fn delete_session(&self, uuid: String) {
-        let mut sessions = self.sessions.lock().unwrap();
-        let position = sessions
+    pub async fn add_ice_candidate(
+        &self,
+        uuid: &str,
+        candidate: RTCIceCandidateInit,
+    ) -> Result<()> {
+        let peer_connection = self
+            .sessions
+            .lock()
+            .unwrap()
             .iter()
-            .position(|s| uuid.eq(s.agent_rtc_uuid.as_str()));
+            .find(|s| uuid.eq(s.agent_rtc_uuid.as_str()))
+            .and_then(|s| s.peer_connection())
+            .ok_or_else(|| anyhow!("session {} not found", uuid))?;
+        peer_connection.add_ice_candidate(candidate).await?;
+        Ok(())
+    }
 
-        match position {
-            Some(position) => {
-                sessions.remove(position);
+    pub async fn delete_session(&self, uuid: String) {
+        match Self::remove_session(&self.sessions, &uuid) {
+            Some(session) => {
+                session.close().await;
                 debug!("Delete session {}", uuid)
             }
             None => {
@@ -76,4 +216,43 @@ impl WebrtcSessionManager {
             }
         };
     }
+
+    fn remove_session(sessions: &SessionList, uuid: &str) -> Option<WebrtcSession> {
+        let mut sessions = sessions.lock().unwrap();
+        let position = sessions
+            .iter()
+            .position(|s| uuid.eq(s.agent_rtc_uuid.as_str()))?;
+
+        Some(sessions.remove(position))
+    }
+}
+
+/// Slot reserved for a session being created, released when dropped.
+struct SessionReservation<'a> {
+    pending_sessions: &'a AtomicUsize,
+}
+
+impl Drop for SessionReservation<'_> {
+    fn drop(&mut self) {
+        self.pending_sessions.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl From<IceServer> for RTCIceServer {
+    fn from(ice_server: IceServer) -> Self {
+        RTCIceServer {
+            urls: ice_server.urls,
+            username: ice_server.username,
+            credential: ice_server.credential,
+        }
+    }
+}
+
+impl From<IceTransportPolicy> for RTCIceTransportPolicy {
+    fn from(policy: IceTransportPolicy) -> Self {
+        match policy {
+            IceTransportPolicy::All => RTCIceTransportPolicy::All,
+            IceTransportPolicy::Relay => RTCIceTransportPolicy::Relay,
+        }
+    }
 }